@@ -1,37 +1,196 @@
-use ::glam::*;
+//! Sprite animations that are defined in data files and advanced by game time.
+//!
+//! The game decides which clip something should play and advances its
+//! [`Animator`] every update. The renderer looks up the current frame of the
+//! clip when it draws, so drawing never changes the animation.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::Path;
+use std::time::Duration;
+
+use ::serde::{Deserialize, Serialize};
+use ::serde_json as json;
 
 use crate::scene::*;
 
-pub enum Transition {
-    Collect(Item),
+/// A single image in a clip.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Frame {
+    /// The name of the frame in the atlas of the clip.
+    pub frame: String,
+    /// How long the frame is shown in milliseconds.
+    pub duration: u64,
 }
 
-pub enum State {
-    Mini,
-    Super,
-    Fire,
+/// A sequence of frames from a single sprite atlas.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Clip {
+    pub atlas: String,
+    pub frames: Vec<Frame>,
+    /// Whether the clip starts over after the last frame. Clips that don't
+    /// loop stay on their last frame.
+    #[serde(default)]
+    pub looping: bool,
 }
 
-pub enum Action {
-    Standing,
-    Running { frame: usize },
-    Jumping { frame: usize },
+impl Clip {
+    pub fn duration(&self) -> Duration {
+        let millis = self.frames.iter().map(|frame| frame.duration).sum();
+        Duration::from_millis(millis)
+    }
+
+    /// Returns the frame that is shown `time` after the clip started.
+    ///
+    /// # Panics
+    /// Panics if the clip doesn't have any frames.
+    pub fn frame_at(&self, time: Duration) -> &Frame {
+        let duration = self.duration().as_millis();
+        let mut time = time.as_millis();
+        if self.looping && duration > 0 {
+            time %= duration;
+        }
+
+        for frame in &self.frames {
+            if time < frame.duration as u128 {
+                return frame;
+            }
+
+            time -= frame.duration as u128;
+        }
+
+        self.frames
+            .last()
+            .expect("Animation clips must have at least one frame")
+    }
 }
 
-pub struct Player {
-    state: State,
-    grounded: bool,
-    action: Action,
+/// All the animation clips by name.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Animations(HashMap<String, Clip>);
+
+impl Animations {
+    pub const PATH: &str = "./assets/animations.json";
+
+    pub fn load(path: &impl AsRef<Path>) -> Result<Self, json::Error> {
+        let file = File::open(path).map_err(json::Error::io)?;
+        json::from_reader(file)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Clip> {
+        self.0.get(name)
+    }
+
+    /// Returns the sprite that `animator` is currently showing, or `None` if
+    /// it hasn't been given a clip yet.
+    ///
+    /// # Panics
+    /// Panics if there is no clip with the name the animator is playing.
+    pub fn sprite(&self, animator: &Animator) -> Option<Sprite> {
+        if !animator.is_playing() {
+            return None;
+        }
+
+        let Some(clip) = self.get(animator.clip) else {
+            panic!("There is no animation clip called \"{}\"", animator.clip);
+        };
+
+        let frame = clip.frame_at(animator.time);
+        let sprite = Sprite::new(&clip.atlas, &frame.frame, animator.mirror);
+        Some(sprite)
+    }
+
+    /// Returns every sprite that is shown by the clips.
+    pub fn sprites(&self) -> impl Iterator<Item = Sprite> + '_ {
+        self.0.values().flat_map(|clip| {
+            let frames = clip.frames.iter();
+            frames.map(|frame| Sprite::new(&clip.atlas, &frame.frame, false))
+        })
+    }
 }
 
-impl ToSprite for Player {
-    fn to_sprite(&self) -> Sprite {
-        let sprites: &'static [()] = match self.state {
-            State::Mini => &[()],
-            State::Super => &[(), ()],
-            State::Fire => &[(), (), ()],
+/// Keeps track of which clip something is playing and how far it has come.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Animator {
+    clip: &'static str,
+    time: Duration,
+    pub mirror: bool,
+}
+
+impl Animator {
+    pub fn new(clip: &'static str) -> Self {
+        Self {
+            clip,
+            ..Default::default()
+        }
+    }
+
+    /// Returns whether a clip has been chosen for the animator yet.
+    pub fn is_playing(&self) -> bool {
+        !self.clip.is_empty()
+    }
+
+    /// Switches to `clip`. The clip is only started over if it isn't already
+    /// playing.
+    pub fn play(&mut self, clip: &'static str) {
+        if self.clip != clip {
+            self.clip = clip;
+            self.time = Duration::ZERO;
+        }
+    }
+
+    pub fn advance(&mut self, delta: Duration) {
+        self.time += delta;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(name: &str, duration: u64) -> Frame {
+        Frame {
+            frame: name.to_owned(),
+            duration,
+        }
+    }
+
+    #[test]
+    fn test_clip_frame_at() {
+        let mut clip = Clip {
+            atlas: String::new(),
+            frames: vec![frame("first", 50), frame("second", 100)],
+            looping: true,
         };
 
-        todo!()
+        assert_eq!(clip.frame_at(Duration::from_millis(0)).frame, "first");
+        assert_eq!(clip.frame_at(Duration::from_millis(50)).frame, "second");
+        assert_eq!(clip.frame_at(Duration::from_millis(160)).frame, "first");
+
+        clip.looping = false;
+        assert_eq!(clip.frame_at(Duration::from_millis(160)).frame, "second");
+    }
+
+    #[test]
+    fn test_animations_file_is_valid() {
+        let animations = Animations::load(&Animations::PATH).unwrap();
+        for clip in animations.0.values() {
+            assert!(!clip.frames.is_empty());
+        }
+
+        // The clips that are played by the player and the enemies.
+        let clips = [
+            "mario_idle",
+            "mario_jump",
+            "mario_run",
+            "goomba_walk",
+            "koopa_walk",
+            "koopa_shell",
+            "piranha_bite",
+        ];
+
+        for clip in clips {
+            assert!(animations.get(clip).is_some(), "{clip} is missing");
+        }
     }
 }
@@ -0,0 +1,401 @@
+//! Loads the textures, audio and levels of the game and keeps track of who is
+//! using them.
+//!
+//! Assets are shared through [`Handle`]s. An asset stays loaded for as long as
+//! there is a handle to it, and is evicted the next time the asset manager is
+//! updated after the last handle has been dropped. Textures and levels are
+//! reloaded when their files change while the game is running.
+
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::mpsc::{self, Receiver};
+use std::{fmt, fs};
+
+use ::image::{Rgba, RgbaImage};
+use ::notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use ::sdl2::pixels::PixelFormatEnum;
+use ::sdl2::render::{BlendMode, Texture, TextureCreator};
+use ::sdl2::video::WindowContext;
+
+use crate::audio::*;
+use crate::level::*;
+
+/// Keeps an asset loaded.
+///
+/// A handle always gives the newest version of its asset, so handles stay
+/// valid when the asset is reloaded.
+pub struct Handle<T>(Rc<Slot<T>>);
+
+struct Slot<T> {
+    path: PathBuf,
+    asset: RefCell<Rc<T>>,
+}
+
+impl<T> Handle<T> {
+    pub fn path(&self) -> &Path {
+        &self.0.path
+    }
+
+    /// Returns the current version of the asset.
+    pub fn get(&self) -> Rc<T> {
+        self.0.asset.borrow().clone()
+    }
+}
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl<T> fmt::Debug for Handle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Handle").field(&self.0.path).finish()
+    }
+}
+
+/// The assets of a single type by the path they were loaded from.
+pub struct Assets<T> {
+    slots: HashMap<PathBuf, Rc<Slot<T>>>,
+}
+
+impl<T> Assets<T> {
+    pub fn new() -> Self {
+        Self {
+            slots: HashMap::new(),
+        }
+    }
+
+    /// Returns a handle to the asset in `path`, loading it with `load` if it
+    /// isn't loaded already.
+    pub fn load<E>(
+        &mut self,
+        path: &Path,
+        load: impl FnOnce(&Path) -> Result<T, E>,
+    ) -> Result<Handle<T>, E> {
+        if let Some(slot) = self.slots.get(path) {
+            return Ok(Handle(slot.clone()));
+        }
+
+        let slot = Rc::new(Slot {
+            path: path.to_owned(),
+            asset: RefCell::new(Rc::new(load(path)?)),
+        });
+
+        self.slots.insert(path.to_owned(), slot.clone());
+        Ok(Handle(slot))
+    }
+
+    /// Replaces the asset in `path` for everyone that has a handle to it and
+    /// returns the old version.
+    pub fn replace(&mut self, path: &Path, asset: T) -> Option<Rc<T>> {
+        let slot = self.slots.get(path)?;
+        Some(slot.asset.replace(Rc::new(asset)))
+    }
+
+    /// Returns the number of handles there are to the asset in `path`.
+    pub fn references(&self, path: &Path) -> usize {
+        // The slot that is kept here doesn't count.
+        self.slots
+            .get(path)
+            .map_or(0, |slot| Rc::strong_count(slot) - 1)
+    }
+
+    pub fn paths(&self) -> impl Iterator<Item = &Path> {
+        self.slots.keys().map(PathBuf::as_path)
+    }
+
+    /// Removes the assets there are no handles to and returns them.
+    pub fn evict(&mut self) -> Vec<(PathBuf, Rc<T>)> {
+        let unused: Vec<_> = self
+            .slots
+            .keys()
+            .filter(|path| self.references(path) == 0)
+            .cloned()
+            .collect();
+
+        unused
+            .into_iter()
+            .map(|path| {
+                let slot = self.slots.remove(&path).unwrap();
+                let asset = slot.asset.borrow().clone();
+                (path, asset)
+            })
+            .collect()
+    }
+}
+
+/// Audio that is kept loaded by the audio manager.
+///
+/// The samples themselves stay with the audio manager, so sounds are still
+/// started by their path.
+#[derive(Debug)]
+pub struct Audio;
+
+struct AssetManagerShared {
+    creator: TextureCreator<WindowContext>,
+    audio: AudioManager,
+    textures: RefCell<Assets<Texture>>,
+    /// The color keys the textures were loaded with, so they can be reloaded
+    /// the same way.
+    color_keys: RefCell<HashMap<PathBuf, Vec<[u8; 3]>>>,
+    levels: RefCell<Assets<Level>>,
+    sounds: RefCell<Assets<Audio>>,
+    /// The directory stops being watched when the watcher is dropped.
+    _watcher: Option<RecommendedWatcher>,
+    /// The files that have changed since the last update.
+    changes: Receiver<PathBuf>,
+}
+
+/// Owns the textures, audio and levels of the game.
+///
+/// Cloning the asset manager gives another handle to the same assets.
+#[derive(Clone)]
+pub struct AssetManager(Rc<AssetManagerShared>);
+
+impl AssetManager {
+    /// The directory that is watched for changes.
+    pub const DIR: &str = "./assets/";
+
+    pub fn new(creator: TextureCreator<WindowContext>, audio: AudioManager) -> Self {
+        let (sender, changes) = mpsc::channel();
+        let watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+            let Ok(event) = event else { return };
+            if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+                for path in event.paths {
+                    let _ = sender.send(path);
+                }
+            }
+        })
+        .and_then(|mut watcher| {
+            watcher.watch(Path::new(Self::DIR), RecursiveMode::Recursive)?;
+            Ok(watcher)
+        });
+
+        let watcher = match watcher {
+            Ok(watcher) => Some(watcher),
+            Err(error) => {
+                eprintln!(
+                    "Changes to the assets won't be reloaded because {} can't be watched: {error}",
+                    Self::DIR
+                );
+                None
+            }
+        };
+
+        Self(Rc::new(AssetManagerShared {
+            creator,
+            audio,
+            textures: RefCell::new(Assets::new()),
+            color_keys: RefCell::default(),
+            levels: RefCell::new(Assets::new()),
+            sounds: RefCell::new(Assets::new()),
+            _watcher: watcher,
+            changes,
+        }))
+    }
+
+    /// Loads the image in `path` as a texture where the colors in `color_key`
+    /// are transparent. Images without a color key keep their own alpha
+    /// channel.
+    ///
+    /// The color key is only used the first time the texture is loaded.
+    pub fn texture(
+        &self,
+        path: &impl AsRef<Path>,
+        color_key: &[[u8; 3]],
+    ) -> Result<Handle<Texture>, String> {
+        let mut textures = self.0.textures.borrow_mut();
+        textures.load(path.as_ref(), |path| {
+            let texture = load_texture(&self.0.creator, path, color_key)?;
+            let mut color_keys = self.0.color_keys.borrow_mut();
+            color_keys.insert(path.to_owned(), color_key.to_vec());
+            Ok(texture)
+        })
+    }
+
+    pub fn level(&self, path: &impl AsRef<Path>) -> Result<Handle<Level>, String> {
+        let mut levels = self.0.levels.borrow_mut();
+        levels.load(path.as_ref(), |path| {
+            read_level(&path).map_err(|error| format!("{}: {error}", path.display()))
+        })
+    }
+
+    /// Starts loading the audio in `path` in the background and keeps it
+    /// loaded for as long as the handle exists.
+    ///
+    /// Audio that hasn't been registered with the audio manager is reported and
+    /// never loaded.
+    pub fn audio(&self, path: &impl AsRef<Path>) -> Handle<Audio> {
+        let mut sounds = self.0.sounds.borrow_mut();
+        let audio = sounds.load(path.as_ref(), |path| {
+            self.0.audio.preload(&path);
+            Ok::<_, ()>(Audio)
+        });
+
+        audio.unwrap()
+    }
+
+    /// Reloads the textures and levels that have changed on disk and evicts
+    /// the assets that are no longer used.
+    ///
+    /// Returns the paths of the assets that were reloaded.
+    pub fn update(&self) -> Vec<PathBuf> {
+        // Saving a file often shows up as several changes, so each file is only
+        // reloaded once.
+        let changes: HashSet<_> = self
+            .0
+            .changes
+            .try_iter()
+            .filter_map(|path| fs::canonicalize(path).ok())
+            .collect();
+
+        let changed = |path: &Path| {
+            let path = fs::canonicalize(path);
+            path.is_ok_and(|path| changes.contains(&path))
+        };
+
+        let mut reloaded = Vec::new();
+        if !changes.is_empty() {
+            let mut textures = self.0.textures.borrow_mut();
+            let paths: Vec<_> = textures
+                .paths()
+                .filter(|path| changed(path))
+                .map(Path::to_owned)
+                .collect();
+            for path in paths {
+                // Files are often written in several steps, so a file that can't be read
+                // is tried again when the rest of it has been written.
+                let color_keys = self.0.color_keys.borrow();
+                match load_texture(&self.0.creator, &path, &color_keys[&path]) {
+                    Ok(texture) => {
+                        if let Some(texture) = textures.replace(&path, texture) {
+                            destroy_texture(texture);
+                        }
+
+                        reloaded.push(path);
+                    }
+                    Err(error) => eprintln!("{error}"),
+                }
+            }
+
+            let mut levels = self.0.levels.borrow_mut();
+            let paths: Vec<_> = levels
+                .paths()
+                .filter(|path| changed(path))
+                .map(Path::to_owned)
+                .collect();
+            for path in paths {
+                match read_level(&path) {
+                    Ok(level) => {
+                        levels.replace(&path, level);
+                        reloaded.push(path);
+                    }
+                    Err(error) => eprintln!("{} could not be reloaded: {error}", path.display()),
+                }
+            }
+        }
+
+        for (path, texture) in self.0.textures.borrow_mut().evict() {
+            self.0.color_keys.borrow_mut().remove(&path);
+            destroy_texture(texture);
+        }
+
+        self.0.levels.borrow_mut().evict();
+
+        for (path, _) in self.0.sounds.borrow_mut().evict() {
+            self.0.audio.unload(&path);
+        }
+
+        reloaded
+    }
+}
+
+/// Textures aren't freed when they are dropped, so this has to be done by hand.
+fn destroy_texture(texture: Rc<Texture>) {
+    // A texture that is still being drawn somewhere is leaked rather than being
+    // destroyed from under it.
+    if let Ok(texture) = Rc::try_unwrap(texture) {
+        unsafe { texture.destroy() };
+    }
+}
+
+fn load_texture(
+    creator: &TextureCreator<WindowContext>,
+    path: &Path,
+    color_key: &[[u8; 3]],
+) -> Result<Texture, String> {
+    let image = image::open(path).map_err(|error| format!("{}: {error}", path.display()))?;
+    let mut image = image.to_rgba8();
+    apply_color_key(&mut image, color_key);
+
+    let mut texture = creator
+        .create_texture_static(PixelFormatEnum::ABGR8888, image.width(), image.height())
+        .map_err(|error| error.to_string())?;
+
+    let pitch = image.width() as usize * std::mem::size_of::<[u8; 4]>();
+    texture
+        .update(None, &image, pitch)
+        .map_err(|error| error.to_string())?;
+    texture.set_blend_mode(BlendMode::Blend);
+
+    Ok(texture)
+}
+
+/// Makes the pixels that have one of the colors in `color_key` transparent.
+fn apply_color_key(image: &mut RgbaImage, color_key: &[[u8; 3]]) {
+    for pixel in image.pixels_mut() {
+        let [r, g, b, _] = pixel.0;
+        if color_key.contains(&[r, g, b]) {
+            *pixel = Rgba([0, 0, 0, 0]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_assets_are_evicted_when_unused() {
+        let mut assets = Assets::new();
+        let path = Path::new("number");
+
+        let first = assets.load(path, |_| Ok::<_, ()>(1)).unwrap();
+        let second = assets.load(path, |_| Ok::<_, ()>(2)).unwrap();
+        assert_eq!(*second.get(), 1);
+        assert_eq!(assets.references(path), 2);
+
+        assets.replace(path, 3);
+        assert_eq!(*first.get(), 3);
+
+        drop(first);
+        assert!(assets.evict().is_empty());
+
+        drop(second);
+        assert_eq!(assets.evict().len(), 1);
+        assert_eq!(assets.references(path), 0);
+    }
+
+    #[test]
+    fn test_color_key_only_clears_matching_pixels() {
+        let mut image = RgbaImage::from_fn(3, 1, |x, _| match x {
+            0 => Rgba([146, 144, 255, 255]),
+            1 => Rgba([146, 144, 254, 255]),
+            _ => Rgba([10, 20, 30, 128]),
+        });
+
+        apply_color_key(&mut image, &[[146, 144, 255]]);
+        assert_eq!(image.get_pixel(0, 0), &Rgba([0, 0, 0, 0]));
+        assert_eq!(image.get_pixel(1, 0), &Rgba([146, 144, 254, 255]));
+        assert_eq!(image.get_pixel(2, 0), &Rgba([10, 20, 30, 128]));
+    }
+}
@@ -0,0 +1,112 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::path::*;
+
+use ::glam::*;
+use ::serde::{Deserialize, Serialize};
+use ::serde_json as json;
+
+/// The part of a sprite sheet that makes up a single frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct AtlasFrame {
+    /// The top-left corner of the frame in the sprite sheet.
+    pub position: UVec2,
+    pub size: UVec2,
+}
+
+/// Names the frames of a single sprite sheet.
+///
+/// Atlases are stored as `.atlas` files next to the sprite sheets they
+/// describe.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Atlas {
+    /// The path of the sprite sheet, relative to the atlas file.
+    pub image: PathBuf,
+    /// The colors in the sprite sheet that are drawn as transparent.
+    ///
+    /// Sprite sheets without a color key use their own alpha channel.
+    #[serde(default)]
+    pub color_key: Vec<[u8; 3]>,
+    pub frames: BTreeMap<String, AtlasFrame>,
+}
+
+pub fn read_atlas(path: &impl AsRef<Path>) -> Result<Atlas, json::Error> {
+    let file = File::open(path).map_err(json::Error::io)?;
+    json::from_reader(file)
+}
+
+/// All the atlases of the game, named by the file stem of their atlas file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Atlases(HashMap<String, Atlas>);
+
+impl Atlases {
+    pub const DIR: &str = "./assets/sprites/";
+
+    /// Reads every `.atlas` file in `dir`.
+    pub fn load_dir(dir: &impl AsRef<Path>) -> Result<Self, json::Error> {
+        let mut atlases = HashMap::new();
+        for entry in fs::read_dir(dir).map_err(json::Error::io)? {
+            let path = entry.map_err(json::Error::io)?.path();
+            if path
+                .extension()
+                .map_or(true, |extension| extension != "atlas")
+            {
+                continue;
+            }
+
+            let mut atlas = read_atlas(&path)?;
+            atlas.image = dir.as_ref().join(&atlas.image);
+
+            let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+            atlases.insert(name, atlas);
+        }
+
+        Ok(Self(atlases))
+    }
+
+    /// Returns the frame named `frame` along with the atlas it's in.
+    pub fn frame(&self, atlas: &str, frame: &str) -> Option<(&Atlas, AtlasFrame)> {
+        let atlas = self.0.get(atlas)?;
+        let frame = atlas.frames.get(frame)?;
+        Some((atlas, *frame))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::animation::*;
+    use crate::map::*;
+    use crate::scene::*;
+
+    #[test]
+    fn test_every_sprite_has_a_frame() {
+        let atlases = Atlases::load_dir(&Atlases::DIR).unwrap();
+        let tiles = TileTable::load(&TileTable::PATH).unwrap();
+        let sprites = tiles.sprites();
+        let animations = Animations::load(&Animations::PATH).unwrap();
+        let entities = [
+            EntityKind::Coin,
+            EntityKind::Pipe { id: 0 },
+            EntityKind::Item(Item::Mushroom),
+            EntityKind::Flag,
+        ];
+        let entities = entities.map(|kind| {
+            let entity = Entity {
+                position: Vec2::ZERO,
+                kind,
+            };
+            entity.to_sprite()
+        });
+
+        for sprite in sprites.chain(animations.sprites()).chain(entities) {
+            let frame = atlases.frame(&sprite.atlas, &sprite.frame);
+            assert!(
+                frame.is_some(),
+                "{}/{} is missing",
+                sprite.atlas,
+                sprite.frame
+            );
+        }
+    }
+}
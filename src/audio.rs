@@ -1,169 +1,1090 @@
+use std::cmp::Reverse;
 use std::collections::HashMap;
+use std::fmt;
 use std::fs::*;
 use std::path::*;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Mutex, RwLock};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-use sdl2::audio::{AudioCVT, AudioCallback, AudioDevice, AudioSpecDesired, AudioSpecWAV};
-use sdl2::AudioSubsystem;
+use futures::executor::ThreadPool;
+use glam::*;
+use sdl2::audio::{AudioCVT, AudioCallback, AudioFormat};
+use serde::{Deserialize, Serialize};
 
-struct Sound {
-    data: Vec<u8>,
+use crate::audio_backend::*;
+use crate::decoder::*;
+
+/// The number of channels the backend plays, which is also the number of
+/// channels the audio is converted to when it's loaded.
+pub const CHANNELS: usize = 2;
+
+/// How important a sound is compared to the other sounds that are playing.
+///
+/// When all voices are in use, a new sound takes over the voice of the least
+/// important sound, as long as that sound isn't more important than the new
+/// one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
+/// Settings for a single sound that is started by the audio manager.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlayOptions {
+    /// A multiplier applied to every sample in the sound.
+    pub volume: f32,
+    pub priority: Priority,
+    /// Whether the sound should start over when it reaches the end instead of
+    /// stopping.
+    pub looping: bool,
+    /// Where in the world the sound comes from.
+    ///
+    /// Positioned sounds are panned and attenuated relative to the listener,
+    /// while other sounds are heard the same in both ears.
+    pub position: Option<Vec2>,
+}
+
+impl PlayOptions {
+    pub fn with_priority(priority: Priority) -> Self {
+        Self {
+            priority,
+            ..Default::default()
+        }
+    }
+
+    pub fn at(position: Vec2) -> Self {
+        Self {
+            position: Some(position),
+            ..Default::default()
+        }
+    }
+}
+
+impl Default for PlayOptions {
+    fn default() -> Self {
+        Self {
+            volume: 1.0,
+            priority: Priority::Normal,
+            looping: false,
+            position: None,
+        }
+    }
+}
+
+/// Where the sounds are heard from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Listener {
+    pub position: Vec2,
+    /// Sounds within this distance of the listener are played at full volume
+    /// and sounds that are this far to one side are panned all the way to that
+    /// side.
+    pub radius: f32,
+}
+
+impl Listener {
+    /// How far sounds are panned towards the side they're on.
+    const MAX_PAN: f32 = 0.8;
+
+    /// Returns the gain of each channel for a sound at `position`.
+    ///
+    /// Beyond the radius of the listener, sounds fade out until they can't be
+    /// heard at three times the radius.
+    fn gains(&self, position: Vec2) -> [f32; CHANNELS] {
+        let offset = position - self.position;
+        let pan = (offset.x / self.radius).clamp(-1.0, 1.0) * Self::MAX_PAN;
+        let gain = (1.0 - (offset.length() / self.radius - 1.0) / 2.0).clamp(0.0, 1.0);
+
+        [gain * (1.0 - pan).min(1.0), gain * (1.0 + pan).min(1.0)]
+    }
+}
+
+impl Default for Listener {
+    fn default() -> Self {
+        Self {
+            position: Vec2::ZERO,
+            radius: f32::INFINITY,
+        }
+    }
+}
+
+/// The volume of a group of sounds.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct Volume {
+    /// The volume between 0 and 1.
+    pub level: f32,
+    pub muted: bool,
+}
+
+impl Volume {
+    /// Returns the multiplier that is applied to the samples of the sounds.
+    pub fn gain(&self) -> f32 {
+        if self.muted {
+            0.0
+        } else {
+            self.level.clamp(0.0, 1.0)
+        }
+    }
+}
+
+impl Default for Volume {
+    fn default() -> Self {
+        Self {
+            level: 1.0,
+            muted: false,
+        }
+    }
+}
+
+/// The volumes of the buses that the sounds are mixed through.
+///
+/// Every sound goes through the master bus, and either the music or the sound
+/// effects bus depending on whether it's played as music.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct AudioSettings {
+    pub master: Volume,
+    pub music: Volume,
+    pub sfx: Volume,
+}
+
+/// Identifies a single sound that has been started by the audio manager.
+///
+/// The handle stays valid after the sound has finished, but all operations on
+/// it are then no-ops.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SoundHandle(u64);
+
+/// A single sound that is being played by the mixer.
+struct Voice {
+    handle: SoundHandle,
+    samples: Arc<[f32]>,
     position: usize,
+    options: PlayOptions,
+    paused: bool,
+    /// The gain of each channel, which is used to place the sound in the world.
+    gains: [f32; CHANNELS],
+
+    /// The gain from fading the voice in or out, and how much it changes pr.
+    /// frame.
+    fade: f32,
+    fade_step: f32,
 }
 
-impl AudioCallback for Sound {
-    type Channel = u8;
+impl Voice {
+    fn new(handle: SoundHandle, samples: Arc<[f32]>, options: PlayOptions) -> Self {
+        Self {
+            handle,
+            samples,
+            position: 0,
+            options,
+            paused: false,
+            gains: [1.0; CHANNELS],
+            fade: 1.0,
+            fade_step: 0.0,
+        }
+    }
+
+    /// Fades the voice from its current gain to `target` over `frames`
+    /// frames.
+    fn fade_to(&mut self, target: f32, frames: usize) {
+        if frames == 0 {
+            self.fade = target;
+            self.fade_step = 0.0;
+        } else {
+            self.fade_step = (target - self.fade) / frames as f32;
+        }
+    }
+
+    fn is_finished(&self) -> bool {
+        let ended = !self.options.looping && self.position >= self.samples.len();
+        let faded_out = self.fade_step <= 0.0 && self.fade <= 0.0;
+        ended || faded_out
+    }
+
+    /// Adds the voice on top of the interleaved frames already in `output`.
+    ///
+    /// The samples are multiplied by `volume` on top of the volume of the
+    /// voice itself.
+    fn mix_into(&mut self, output: &mut [f32], volume: f32) {
+        if self.paused || self.samples.len() < CHANNELS {
+            return;
+        }
+
+        for frame in output.chunks_exact_mut(CHANNELS) {
+            if self.position + CHANNELS > self.samples.len() {
+                if !self.options.looping {
+                    break;
+                }
+
+                self.position = 0;
+            }
+
+            let samples = &self.samples[self.position..self.position + CHANNELS];
+            for ((out, sample), gain) in frame.iter_mut().zip(samples).zip(self.gains) {
+                *out += sample * gain * volume * self.options.volume * self.fade;
+            }
+
+            self.position += CHANNELS;
+            self.fade = (self.fade + self.fade_step).clamp(0.0, 1.0);
+        }
+    }
+}
+
+/// Mixes all the sounds that are currently playing into a single stream.
+///
+/// Music is kept apart from the other sounds so that it never has its voice
+/// stolen and so that a new track can crossfade with the previous one.
+pub struct Mixer {
+    voices: Vec<Voice>,
+    music: Vec<Voice>,
+    settings: AudioSettings,
+}
+
+impl Mixer {
+    /// The maximum number of sounds that can be played at the same time.
+    const MAX_VOICES: usize = 16;
+
+    pub fn new() -> Self {
+        Self {
+            voices: Vec::with_capacity(Self::MAX_VOICES),
+            music: Vec::with_capacity(2),
+            settings: AudioSettings::default(),
+        }
+    }
+
+    /// Stops every sound and the music at once, without fading anything out.
+    pub fn clear(&mut self) {
+        self.voices.clear();
+        self.music.clear();
+    }
+
+    fn find_mut(&mut self, handle: SoundHandle) -> Option<&mut Voice> {
+        self.voices
+            .iter_mut()
+            .chain(self.music.iter_mut())
+            .find(|voice| voice.handle == handle)
+    }
+
+    fn stop(&mut self, handle: SoundHandle) {
+        self.voices.retain(|voice| voice.handle != handle);
+        self.music.retain(|voice| voice.handle != handle);
+    }
+
+    /// Starts playing `voice` as the music, fading out the current music over
+    /// `fade` frames while fading in the new one.
+    fn play_music(&mut self, mut voice: Voice, fade: usize) {
+        self.stop_music(fade);
+
+        if fade > 0 {
+            voice.fade = 0.0;
+            voice.fade_to(1.0, fade);
+        }
+
+        self.music.push(voice);
+    }
+
+    fn stop_music(&mut self, fade: usize) {
+        for music in &mut self.music {
+            music.fade_to(0.0, fade);
+        }
+
+        self.music.retain(|voice| !voice.is_finished());
+    }
+
+    fn current_music(&mut self) -> Option<&mut Voice> {
+        self.music
+            .iter_mut()
+            .rev()
+            .find(|voice| voice.fade_step >= 0.0)
+    }
+
+    /// Adds a voice to the mixer.
+    ///
+    /// If every voice is in use the least important voice is stolen. When
+    /// there are multiple candidates the one that has played the longest is
+    /// chosen. If all voices are more important than `voice`, it's discarded.
+    fn play(&mut self, voice: Voice) {
+        if self.voices.len() < Self::MAX_VOICES {
+            self.voices.push(voice);
+            return;
+        }
+
+        let candidate = self
+            .voices
+            .iter_mut()
+            .filter(|other| other.options.priority <= voice.options.priority)
+            .min_by_key(|other| (other.options.priority, Reverse(other.position)));
+
+        if let Some(candidate) = candidate {
+            *candidate = voice;
+        }
+    }
+}
+
+impl AudioCallback for Mixer {
+    type Channel = f32;
 
     fn callback(&mut self, output: &mut [Self::Channel]) {
-        let Self { data, position } = self;
+        output.fill(0.0);
 
-        for item in output {
-            // [0, 255] -> [-128, 127]
-            let point = data.get(*position).map(|p| *p as f32).unwrap_or(0f32) - 128f32;
-            let scaled = (point + 128f32) as u8;
-            *item = scaled;
-            *position += 1;
+        let AudioSettings { master, music, sfx } = self.settings;
+        for voice in &mut self.voices {
+            voice.mix_into(output, master.gain() * sfx.gain());
         }
+
+        for voice in &mut self.music {
+            voice.mix_into(output, master.gain() * music.gain());
+        }
+
+        // Sounds that are played at the same time can add up to more than the device
+        // can handle, so we clip them instead of letting them wrap around.
+        for out in output.iter_mut() {
+            *out = out.clamp(-1.0, 1.0);
+        }
+
+        self.voices.retain(|voice| !voice.is_finished());
+        self.music.retain(|voice| !voice.is_finished());
     }
 }
 
+/// The state of a piece of registered audio.
+#[derive(Clone)]
+enum Clip {
+    Unloaded,
+    Loading,
+    /// The samples of the audio, converted to the format of the backend.
+    Loaded(Arc<[f32]>),
+    Failed,
+}
+
+/// A sound that was started before its audio had finished loading.
+struct PendingSound {
+    handle: SoundHandle,
+    path: PathBuf,
+    options: PlayOptions,
+    paused: bool,
+    /// The number of frames the sound fades in over, if it's music.
+    music: Option<usize>,
+}
+
+/// The reasons a file can't be registered with the audio manager.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RegisterError {
+    NotAFile(PathBuf),
+    /// There is no decoder for the extension of the file.
+    UnsupportedFormat(PathBuf),
+}
+
+impl fmt::Display for RegisterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotAFile(path) => write!(f, "{} isn't a file", path.display()),
+            Self::UnsupportedFormat(path) => {
+                write!(f, "{} isn't in a supported audio format", path.display())
+            }
+        }
+    }
+}
+
+impl std::error::Error for RegisterError {}
+
+/// The locks are taken in the order `music`, `backend` and then `pending`, so
+/// that two threads never wait on each other.
 struct AudioManagerShared {
-    system: AudioSubsystem,
-    device: Mutex<Option<AudioDevice<Sound>>>,
-    files: RwLock<HashMap<PathBuf, (Option<AudioSpecWAV>, bool)>>,
+    backend: Mutex<Box<dyn AudioBackend>>,
+    /// The number of frames the backend plays pr. second.
+    freq: i32,
+    thread_pool: ThreadPool,
+    decoders: RwLock<Decoders>,
+    clips: Arc<RwLock<HashMap<PathBuf, Clip>>>,
+    /// The audio that is waiting to be loaded along with when it's needed.
+    queue: Mutex<Vec<(Instant, PathBuf)>>,
+    pending: Mutex<Vec<PendingSound>>,
+    next_handle: AtomicU64,
+    music: Mutex<Option<(PathBuf, SoundHandle)>>,
+    listener: Mutex<Listener>,
 }
 
 /// This class handles the loading and playing of audio.
-// TODO Should probably use a channel.
+///
+/// All sounds are mixed together and played through a single backend, so
+/// starting a new sound doesn't interrupt the ones that are already playing.
+///
+/// Audio is decoded on a thread pool, so starting a sound never blocks. A
+/// sound that is started before its audio has been loaded starts playing as
+/// soon as the audio is ready.
+///
+/// Cloning the audio manager gives another handle to the same audio.
+#[derive(Clone)]
 pub struct AudioManager(Arc<AudioManagerShared>);
 
 impl AudioManager {
-    /// Creates a new audio manager.
-    pub fn new(system: AudioSubsystem) -> Self {
+    /// The maximum number of files that are loaded at the same time.
+    const MAX_LOADS: usize = 4;
+
+    /// Creates a new audio manager that plays audio through `backend` and
+    /// loads audio on `thread_pool`.
+    pub fn new(backend: impl AudioBackend + 'static, thread_pool: ThreadPool) -> Self {
+        let freq = backend.freq();
+
         Self(Arc::new(AudioManagerShared {
-            system,
-            device: Mutex::new(None),
-            files: RwLock::new(HashMap::new()),
+            backend: Mutex::new(Box::new(backend)),
+            freq,
+            thread_pool,
+            decoders: RwLock::default(),
+            clips: Arc::default(),
+            queue: Mutex::new(Vec::new()),
+            pending: Mutex::new(Vec::new()),
+            next_handle: AtomicU64::new(0),
+            music: Mutex::new(None),
+            listener: Mutex::default(),
         }))
     }
 
     /// Updates the audio manager.
     /// This function is called once pr. frame by the underlying "engine".
     pub fn update(&mut self) {
-        let mut device = self.0.device.lock().unwrap();
-        if let Some(device) = device.as_mut() {
-            device.resume();
+        {
+            let mut backend = self.0.backend.lock().unwrap();
+            backend.update();
+
+            // Start the sounds whose audio has finished loading since last frame.
+            let clips = self.0.clips.read().unwrap();
+            let mut pending = self.0.pending.lock().unwrap();
+            let mut mixer = backend.lock();
+            pending.retain(|sound| {
+                let samples = match &clips[&sound.path] {
+                    Clip::Loaded(samples) => samples.clone(),
+                    Clip::Failed => return false,
+                    Clip::Unloaded | Clip::Loading => return true,
+                };
+
+                let mut voice = self.voice(sound.handle, samples, sound.options);
+                voice.paused = sound.paused;
+                match sound.music {
+                    Some(fade) => mixer.play_music(voice, fade),
+                    None => mixer.play(voice),
+                }
+
+                false
+            });
         }
+
+        self.dispatch();
+    }
+
+    /// Returns the volumes that the sounds are played with.
+    pub fn settings(&self) -> AudioSettings {
+        let mut backend = self.0.backend.lock().unwrap();
+        let settings = backend.lock().settings;
+        settings
+    }
+
+    /// Changes the volumes of the sounds, including the ones that are already
+    /// playing.
+    pub fn set_settings(&self, settings: AudioSettings) {
+        let mut backend = self.0.backend.lock().unwrap();
+        backend.lock().settings = settings;
+    }
+
+    /// Moves the listener that positioned sounds are heard from.
+    ///
+    /// Sounds that are already playing keep the position they were heard from
+    /// when they started.
+    pub fn set_listener(&self, listener: Listener) {
+        *self.0.listener.lock().unwrap() = listener;
+    }
+
+    /// Adds a decoder for the files that end with `extension`.
+    ///
+    /// WAV, OGG, FLAC and MP3 files are supported out of the box. Files must
+    /// be registered after the decoder for their format has been added.
+    pub fn add_decoder(&self, extension: &str, decoder: impl Decoder + 'static) {
+        let mut decoders = self.0.decoders.write().unwrap();
+        decoders.insert(extension, decoder);
     }
 
     /// Registers an entire directory for the manager.
     ///
     /// Setting `recurse` to true will also register all the files in nested
-    /// directories.
-    pub fn register_dir(&self, path: &impl AsRef<Path>, recurse: bool) {
+    /// directories. Files that aren't in a supported audio format are skipped,
+    /// and the reasons they were skipped are returned.
+    pub fn register_dir(&self, path: &impl AsRef<Path>, recurse: bool) -> Vec<RegisterError> {
+        let mut errors = Vec::new();
+
         let entries = read_dir(path).unwrap();
         for entry in entries {
             let entry = entry.unwrap();
             let path = entry.path();
             if path.is_dir() && recurse {
-                self.register_dir(&path, true);
-            } else if path.is_file() {
-                let mut files = self.0.files.write().unwrap();
-                files.insert(path, (None, false));
+                errors.extend(self.register_dir(&path, true));
+            } else if path.is_file()
+                && let Err(error) = self.register(&path)
+            {
+                errors.push(error);
             }
         }
+
+        errors
     }
 
     /// Registers a piece of audio so that the audio manager knows where to find
     /// it.
     ///
-    /// # Panics
-    /// Panics if `path` isn't a path to a file.
-    pub fn register(&self, path: &impl AsRef<Path>) {
+    /// Fails if `path` isn't a file or if there is no decoder for its format.
+    pub fn register(&self, path: &impl AsRef<Path>) -> Result<(), RegisterError> {
         let path = path.as_ref().to_owned();
-        assert!(path.is_file());
+        if !path.is_file() {
+            return Err(RegisterError::NotAFile(path));
+        }
+
+        if self.0.decoders.read().unwrap().get(&path).is_none() {
+            return Err(RegisterError::UnsupportedFormat(path));
+        }
 
-        let mut files = self.0.files.write().unwrap();
-        files.insert(path, (None, false));
+        let mut clips = self.0.clips.write().unwrap();
+        clips.entry(path).or_insert(Clip::Unloaded);
+        Ok(())
     }
 
     /// Returns whether the `path` has been registered with the audio manager.
     ///
     /// This can be both files and directories.
     pub fn registered(&self, path: &impl AsRef<Path>) -> bool {
-        let files = self.0.files.read().unwrap();
-        files.contains_key(path.as_ref())
+        let clips = self.0.clips.read().unwrap();
+        clips.contains_key(path.as_ref())
     }
 
     /// Tells the audio manager that this piece of audio will soon be used and
     /// it should be loaded in the backgorund.
     ///
-    /// Internally calls `preload_with_time(path, Duration::SECOND)`
+    /// Internally calls `preload_with_time(path, Duration::from_secs(1))`
     ///
-    /// # Panics
-    ///
-    /// - Panics if `path` hasn't been registered with the audio manager.
-    /// - Panics if `path` isn't a path to a file.
+    /// Audio that hasn't been registered is reported and skipped.
     pub fn preload(&self, path: &impl AsRef<Path>) {
-        assert!(path.as_ref().is_file());
+        self.preload_with_time(path, Duration::from_secs(1));
+    }
 
-        let mut files = self.0.files.write().unwrap();
-        let (stream, _) = files.get_mut(path.as_ref()).unwrap();
-        if stream.is_none() {
-            *stream = Some(sdl2::audio::AudioSpecWAV::load_wav(path).unwrap())
-        }
+    /// Tells the audio manager that this piece of audio will be used within
+    /// `time` and it should be loaded in the background.
+    ///
+    /// Only a few files are loaded at the same time, and the audio that is
+    /// needed the soonest is loaded first.
+    ///
+    /// Audio that hasn't been registered is reported and skipped.
+    pub fn preload_with_time(&self, path: &impl AsRef<Path>, time: Duration) {
+        self.request(path.as_ref(), Instant::now() + time);
     }
 
-    pub fn preload_with_time(&self, path: &impl AsRef<Path>, time: Duration) {}
+    /// Frees the samples of the audio in `path`. The audio is loaded again the
+    /// next time it's needed, and sounds that are already playing keep playing.
+    pub fn unload(&self, path: &impl AsRef<Path>) {
+        let mut clips = self.0.clips.write().unwrap();
+        if let Some(clip @ Clip::Loaded(_)) = clips.get_mut(path.as_ref()) {
+            *clip = Clip::Unloaded;
+        }
+    }
 
     /// Starts playing the audio in `path`
     ///
-    /// # Panics
-    /// - Panics if `path` hasn't been registered with the audio manager.
-    /// - Panics if `path` isn't pointing to a file.
-    pub fn start(&self, path: &impl AsRef<Path>) {
-        let mut files = self.0.files.write().unwrap();
+    /// Internally calls `start_with(path, PlayOptions::default())`
+    ///
+    /// Nothing is played if `path` hasn't been registered with the audio
+    /// manager.
+    pub fn start(&self, path: &impl AsRef<Path>) -> SoundHandle {
+        self.start_with(path, PlayOptions::default())
+    }
 
-        let (stream, _) = files.get_mut(path.as_ref()).unwrap();
-        let stream = if let Some(stream) = stream.as_mut() {
-            stream
-        } else {
-            let stream = AudioSpecWAV::load_wav(path).unwrap();
-            files.insert((path.as_ref()).to_owned(), (Some(stream), false));
-            files.get_mut(path.as_ref()).unwrap().0.as_mut().unwrap()
+    /// Starts playing the audio in `path` with the given volume and priority.
+    ///
+    /// If the audio hasn't been loaded yet, it starts playing once it has.
+    ///
+    /// Nothing is played if `path` hasn't been registered with the audio
+    /// manager.
+    pub fn start_with(&self, path: &impl AsRef<Path>, options: PlayOptions) -> SoundHandle {
+        self.play(path.as_ref(), options, None)
+    }
+
+    /// Stops playing the sound.
+    ///
+    /// This function is a no-op if the sound has already finished.
+    pub fn stop(&self, sound: SoundHandle) {
+        let mut pending = self.0.pending.lock().unwrap();
+        pending.retain(|pending| pending.handle != sound);
+        drop(pending);
+
+        let mut backend = self.0.backend.lock().unwrap();
+        backend.lock().stop(sound);
+    }
+
+    /// Pauses the sound until `resume` is called with the same handle.
+    ///
+    /// This function is a no-op if the sound has already finished.
+    pub fn pause(&self, sound: SoundHandle) {
+        self.set_pending_paused(sound, true);
+
+        let mut backend = self.0.backend.lock().unwrap();
+        if let Some(voice) = backend.lock().find_mut(sound) {
+            voice.paused = true;
+        };
+    }
+
+    /// Continues playing a sound that has been paused.
+    ///
+    /// This function is a no-op if the sound has already finished.
+    pub fn resume(&self, sound: SoundHandle) {
+        self.set_pending_paused(sound, false);
+
+        let mut backend = self.0.backend.lock().unwrap();
+        if let Some(voice) = backend.lock().find_mut(sound) {
+            voice.paused = false;
+        };
+    }
+
+    /// Returns whether the sound is still playing, paused or waiting for its
+    /// audio to load.
+    pub fn is_playing(&self, sound: SoundHandle) -> bool {
+        let pending = self.0.pending.lock().unwrap();
+        if pending.iter().any(|pending| pending.handle == sound) {
+            return true;
+        }
+
+        drop(pending);
+        let mut backend = self.0.backend.lock().unwrap();
+        let playing = backend.lock().find_mut(sound).is_some();
+        playing
+    }
+
+    /// Starts looping the audio in `path` on the music channel.
+    ///
+    /// The music that is already playing fades out over `fade` while the new
+    /// music fades in. Nothing happens if `path` is already the music that is
+    /// playing.
+    ///
+    /// Nothing is played if `path` hasn't been registered with the audio
+    /// manager.
+    pub fn play_music(&self, path: &impl AsRef<Path>, fade: Duration) -> SoundHandle {
+        let mut music = self.0.music.lock().unwrap();
+        if let Some((current, handle)) = music.as_ref()
+            && current == path.as_ref()
+            && self.is_playing(*handle)
+        {
+            return *handle;
+        }
+
+        let options = PlayOptions {
+            looping: true,
+            ..Default::default()
+        };
+
+        self.forget_pending_music();
+        let handle = self.play(path.as_ref(), options, Some(self.frames_in(fade)));
+
+        *music = Some((path.as_ref().to_owned(), handle));
+        handle
+    }
+
+    /// Fades out the music that is playing over `fade`.
+    pub fn stop_music(&self, fade: Duration) {
+        // The music is locked before the backend, like in `play_music`.
+        let mut music = self.0.music.lock().unwrap();
+        self.forget_pending_music();
+
+        let mut backend = self.0.backend.lock().unwrap();
+        backend.lock().stop_music(self.frames_in(fade));
+        *music = None;
+    }
+
+    /// Pauses the music without forgetting where it was.
+    pub fn pause_music(&self) {
+        let mut backend = self.0.backend.lock().unwrap();
+        if let Some(music) = backend.lock().current_music() {
+            music.paused = true;
         };
+    }
 
-        let spec = AudioSpecDesired {
-            freq: Some(44_100),
-            channels: Some(1),
-            samples: None,
+    /// Continues playing the music after it has been paused.
+    pub fn resume_music(&self) {
+        let mut backend = self.0.backend.lock().unwrap();
+        if let Some(music) = backend.lock().current_music() {
+            music.paused = false;
         };
+    }
+
+    fn next_handle(&self) -> SoundHandle {
+        SoundHandle(self.0.next_handle.fetch_add(1, Ordering::Relaxed))
+    }
 
-        let device = self.0.system.open_playback(None, &spec, |spec| {
-            let converter = AudioCVT::new(
-                stream.format,
-                stream.channels,
-                stream.freq,
-                spec.format,
-                spec.channels,
-                spec.freq,
-            )
-            .unwrap();
-            let data = converter.convert(stream.buffer().to_vec());
+    /// Returns the number of frames the backend plays in `duration`.
+    fn frames_in(&self, duration: Duration) -> usize {
+        (duration.as_secs_f32() * self.0.freq as f32) as usize
+    }
 
-            Sound { data, position: 0 }
-        });
+    /// Creates a voice that is placed in the world relative to the listener.
+    fn voice(&self, handle: SoundHandle, samples: Arc<[f32]>, options: PlayOptions) -> Voice {
+        let mut voice = Voice::new(handle, samples, options);
+        if let Some(position) = options.position {
+            voice.gains = self.0.listener.lock().unwrap().gains(position);
+        }
 
-        *self.0.device.lock().unwrap() = Some(device.unwrap());
+        voice
     }
 
-    /// Stops playing the audio in `path`
+    /// Returns the state of the audio in `path`.
     ///
-    /// This function is a no-op if the audio is not playing.
+    /// Audio that hasn't been registered is reported and treated like audio
+    /// that failed to load, since the paths often come from level files.
+    fn clip(&self, path: &Path) -> Clip {
+        let clips = self.0.clips.read().unwrap();
+        match clips.get(path) {
+            Some(clip) => clip.clone(),
+            None => {
+                eprintln!("{path:?} hasn't been registered with the audio manager");
+                Clip::Failed
+            }
+        }
+    }
+
+    /// Plays the audio in `path` right away if it has been loaded, otherwise
+    /// the sound is started once the audio has been loaded.
     ///
-    /// # Panics
-    /// - Panics if `path` hasn't been registered with the audio manager.
-    /// - Panics if `path` isn't pointing to a file.
-    pub fn stop(&self, path: &impl AsRef<Path>) {}
+    /// The sound is played as music when `music` is the number of samples it
+    /// should fade in over.
+    fn play(&self, path: &Path, options: PlayOptions, music: Option<usize>) -> SoundHandle {
+        let handle = self.next_handle();
+        let clip = self.clip(path);
+
+        let mut backend = self.0.backend.lock().unwrap();
+        backend.started(path, &options);
+
+        match clip {
+            Clip::Loaded(samples) => {
+                let voice = self.voice(handle, samples, options);
+                match music {
+                    Some(fade) => backend.lock().play_music(voice, fade),
+                    None => backend.lock().play(voice),
+                }
+            }
+            Clip::Failed => {}
+            Clip::Unloaded | Clip::Loading => {
+                drop(backend);
+
+                let mut pending = self.0.pending.lock().unwrap();
+                pending.push(PendingSound {
+                    handle,
+                    path: path.to_owned(),
+                    options,
+                    paused: false,
+                    music,
+                });
+
+                drop(pending);
+                self.request(path, Instant::now());
+            }
+        }
+
+        handle
+    }
+
+    fn set_pending_paused(&self, sound: SoundHandle, paused: bool) {
+        let mut pending = self.0.pending.lock().unwrap();
+        if let Some(pending) = pending.iter_mut().find(|pending| pending.handle == sound) {
+            pending.paused = paused;
+        }
+    }
+
+    /// Makes sure music that is still loading doesn't start playing.
+    fn forget_pending_music(&self) {
+        let mut pending = self.0.pending.lock().unwrap();
+        pending.retain(|pending| pending.music.is_none());
+    }
+
+    /// Queues the audio in `path` to be loaded before `deadline`.
+    fn request(&self, path: &Path, deadline: Instant) {
+        if !matches!(self.clip(path), Clip::Unloaded) {
+            return;
+        }
+
+        let mut queue = self.0.queue.lock().unwrap();
+        match queue.iter_mut().find(|(_, queued)| queued == path) {
+            Some((queued, _)) => *queued = deadline.min(*queued),
+            None => queue.push((deadline, path.to_owned())),
+        }
+
+        drop(queue);
+        self.dispatch();
+    }
+
+    /// Starts loading the queued audio that is needed the soonest, as long as
+    /// fewer than `MAX_LOADS` files are being loaded.
+    fn dispatch(&self) {
+        let mut clips = self.0.clips.write().unwrap();
+        let mut queue = self.0.queue.lock().unwrap();
+        let mut loading = clips
+            .values()
+            .filter(|clip| matches!(clip, Clip::Loading))
+            .count();
+
+        queue.sort_by_key(|(deadline, _)| Reverse(*deadline));
+        while loading < Self::MAX_LOADS {
+            let Some((_, path)) = queue.pop() else { break };
+            let Some(clip @ Clip::Unloaded) = clips.get_mut(&path) else {
+                continue;
+            };
+
+            *clip = Clip::Loading;
+            loading += 1;
+
+            let clips = self.0.clips.clone();
+            let decoder = self.0.decoders.read().unwrap().get(&path);
+            let freq = self.0.freq;
+            self.0.thread_pool.spawn_ok(async move {
+                let decoded = match decoder {
+                    Some(decoder) => decoder.decode(&path),
+                    None => Err("There is no decoder for the format".to_owned()),
+                };
+
+                let clip = match decoded.and_then(|audio| convert(audio, freq)) {
+                    Ok(samples) => Clip::Loaded(samples.into()),
+                    Err(error) => {
+                        eprintln!("Failed to load {}: {error}", path.display());
+                        Clip::Failed
+                    }
+                };
+
+                clips.write().unwrap().insert(path, clip);
+            });
+        }
+    }
+}
+
+/// Converts decoded audio to the format the backend plays at `freq`.
+fn convert(audio: DecodedAudio, freq: i32) -> Result<Vec<f32>, String> {
+    let converter = AudioCVT::new(
+        audio.format,
+        audio.channels,
+        audio.freq,
+        AudioFormat::f32_sys(),
+        CHANNELS as u8,
+        freq,
+    )?;
+
+    let data = converter.convert(audio.data);
+    let samples = data
+        .chunks_exact(std::mem::size_of::<f32>())
+        .map(|bytes| f32::from_ne_bytes(bytes.try_into().unwrap()))
+        .collect();
+
+    Ok(samples)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sound::*;
+
+    fn voice(len: usize, position: usize, priority: Priority) -> Voice {
+        let options = PlayOptions::with_priority(priority);
+        let mut voice = Voice::new(SoundHandle(0), vec![0.5; len].into(), options);
+        voice.position = position;
+        voice
+    }
+
+    #[test]
+    fn test_mixer_steals_least_important_voice() {
+        let mut mixer = Mixer::new();
+        for i in 0..Mixer::MAX_VOICES {
+            let priority = if i == 3 {
+                Priority::Low
+            } else {
+                Priority::Normal
+            };
+            mixer.play(voice(100, i, priority));
+        }
+
+        mixer.play(voice(10, 0, Priority::Normal));
+        assert_eq!(mixer.voices.len(), Mixer::MAX_VOICES);
+        assert_eq!(mixer.voices[3].samples.len(), 10);
+
+        // Every voice is more important than a low priority sound, so it's discarded.
+        mixer.play(voice(20, 0, Priority::Low));
+        assert!(mixer.voices.iter().all(|voice| voice.samples.len() != 20));
+
+        // The voice that has played the longest is stolen when there are multiple
+        // voices with the same priority.
+        mixer.play(voice(30, 0, Priority::High));
+        assert_eq!(mixer.voices[Mixer::MAX_VOICES - 1].samples.len(), 30);
+    }
+
+    #[test]
+    fn test_mixer_clips_and_removes_finished_voices() {
+        let mut mixer = Mixer::new();
+        mixer.play(voice(4, 0, Priority::Normal));
+        mixer.play(voice(4, 0, Priority::Normal));
+        mixer.play(voice(8, 0, Priority::Normal));
+
+        let mut output = [0.0; 4];
+        mixer.callback(&mut output);
+        assert_eq!(output, [1.0; 4]);
+        assert_eq!(mixer.voices.len(), 1);
+    }
+
+    #[test]
+    fn test_mixer_applies_bus_volumes() {
+        let mut mixer = Mixer::new();
+        mixer.settings.master.level = 0.5;
+        mixer.settings.sfx.level = 0.5;
+        mixer.settings.music.muted = true;
+
+        mixer.play(Voice::new(
+            SoundHandle(0),
+            vec![1.0; 2].into(),
+            PlayOptions::default(),
+        ));
+        mixer.play_music(
+            Voice::new(SoundHandle(1), vec![1.0; 2].into(), PlayOptions::default()),
+            0,
+        );
+
+        let mut output = [0.0; 2];
+        mixer.callback(&mut output);
+        assert_eq!(output, [0.25, 0.25]);
+    }
+
+    #[test]
+    fn test_music_crossfade() {
+        let mut mixer = Mixer::new();
+        mixer.play_music(
+            Voice::new(SoundHandle(0), vec![1.0; 2].into(), PlayOptions::default()),
+            0,
+        );
+
+        let mut output = [0.0; 4];
+        mixer.callback(&mut output);
+        assert_eq!(output, [1.0, 1.0, 0.0, 0.0]);
+
+        let options = PlayOptions {
+            looping: true,
+            ..Default::default()
+        };
+
+        mixer.play_music(Voice::new(SoundHandle(1), vec![1.0; 2].into(), options), 0);
+        mixer.play_music(Voice::new(SoundHandle(2), vec![0.5; 2].into(), options), 4);
+
+        let mut output = [0.0; 8];
+        mixer.callback(&mut output);
+        assert_eq!(output, [1.0, 1.0, 0.875, 0.875, 0.75, 0.75, 0.625, 0.625]);
+
+        // The previous track is removed once it has faded out completely.
+        assert_eq!(mixer.music.len(), 1);
+        assert_eq!(mixer.music[0].handle, SoundHandle(2));
+    }
+
+    /// Updates the audio manager until the audio of `sound` has loaded and
+    /// the sound has been handed to the mixer.
+    fn wait_until_started(audio: &mut AudioManager, sound: SoundHandle) {
+        for _ in 0..500 {
+            audio.update();
+
+            let pending = audio.0.pending.lock().unwrap();
+            if !pending.iter().any(|pending| pending.handle == sound) {
+                return;
+            }
+
+            drop(pending);
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        panic!("The audio of the sound was never loaded");
+    }
+
+    fn is_paused(audio: &AudioManager, sound: SoundHandle) -> bool {
+        let mut backend = audio.0.backend.lock().unwrap();
+        let paused = backend.lock().find_mut(sound).unwrap().paused;
+        paused
+    }
+
+    #[test]
+    fn test_sounds_can_be_paused_while_loading() {
+        let mut audio = AudioManager::new(RecordingBackend::new(), ThreadPool::new().unwrap());
+        audio.register(&Sound::JumpSmall).unwrap();
+
+        let sound = audio.start(&Sound::JumpSmall);
+        audio.pause(sound);
+        wait_until_started(&mut audio, sound);
+        assert!(is_paused(&audio, sound));
+
+        // Paused sounds don't advance, so they never finish.
+        for _ in 0..600 {
+            audio.update();
+        }
+
+        assert!(audio.is_playing(sound));
+
+        audio.resume(sound);
+        assert!(!is_paused(&audio, sound));
+        for _ in 0..600 {
+            audio.update();
+        }
+
+        assert!(!audio.is_playing(sound));
+    }
+
+    #[test]
+    fn test_sounds_that_are_resumed_while_loading_play() {
+        let mut audio = AudioManager::new(RecordingBackend::new(), ThreadPool::new().unwrap());
+        audio.register(&Sound::JumpSmall).unwrap();
+
+        let sound = audio.start(&Sound::JumpSmall);
+        audio.pause(sound);
+        audio.resume(sound);
+        wait_until_started(&mut audio, sound);
+        assert!(!is_paused(&audio, sound));
+
+        // Pausing one sound leaves the others playing.
+        let other = audio.start(&Sound::JumpSmall);
+        audio.pause(other);
+        assert!(is_paused(&audio, other));
+        assert!(!is_paused(&audio, sound));
+    }
+
+    #[test]
+    fn test_register_rejects_files_that_arent_audio() {
+        let audio = AudioManager::new(RecordingBackend::new(), ThreadPool::new().unwrap());
+
+        let path = Path::new("./assets/levels/Level 2.level");
+        assert_eq!(
+            audio.register(&path),
+            Err(RegisterError::UnsupportedFormat(path.to_owned()))
+        );
+        assert!(!audio.registered(&path));
+
+        let errors = audio.register_dir(&"./assets", true);
+        assert!(audio.registered(&"./assets/audio/sfx/jump_small.wav"));
+        assert!(!audio.registered(&path));
+        assert!(errors.contains(&RegisterError::UnsupportedFormat(path.to_owned())));
+    }
+
+    #[test]
+    fn test_listener_pans_and_attenuates() {
+        let listener = Listener {
+            position: vec2(200.0, 144.0),
+            radius: 200.0,
+        };
+
+        assert_eq!(listener.gains(vec2(200.0, 144.0)), [1.0, 1.0]);
+
+        let [left, right] = listener.gains(vec2(400.0, 144.0));
+        assert!(left < right);
+        assert_eq!(right, 1.0);
+
+        // Sounds that are off-screen are quieter, and far away sounds are silent.
+        let [left, right] = listener.gains(vec2(-200.0, 144.0));
+        assert!(left < 1.0 && right < left);
+        assert_eq!(listener.gains(vec2(1000.0, 144.0)), [0.0, 0.0]);
+    }
 }
@@ -0,0 +1,204 @@
+use std::ops::DerefMut;
+use std::path::Path;
+#[cfg(test)]
+use std::path::PathBuf;
+#[cfg(test)]
+use std::sync::{Arc, Mutex};
+
+#[cfg(test)]
+use sdl2::audio::AudioCallback;
+use sdl2::audio::{AudioDevice, AudioSpecDesired};
+use sdl2::AudioSubsystem;
+
+use crate::audio::*;
+
+/// Where the audio that is mixed by the audio manager ends up.
+pub trait AudioBackend {
+    /// The number of frames the backend plays pr. second.
+    fn freq(&self) -> i32;
+
+    /// Gives exclusive access to the mixer that feeds the backend.
+    fn lock(&mut self) -> Box<dyn DerefMut<Target = Mixer> + '_>;
+
+    /// Called once pr. frame by the audio manager.
+    fn update(&mut self) {}
+
+    /// Called whenever a sound is started, even if its audio hasn't been
+    /// loaded yet.
+    fn started(&mut self, _path: &Path, _options: &PlayOptions) {}
+}
+
+/// Plays the audio through an SDL audio device.
+pub struct SdlBackend {
+    _system: AudioSubsystem,
+    device: AudioDevice<Mixer>,
+}
+
+impl SdlBackend {
+    /// Opens the default audio device.
+    ///
+    /// Fails if there is no audio device that can be opened.
+    pub fn open(system: AudioSubsystem) -> Result<Self, String> {
+        let spec = AudioSpecDesired {
+            freq: Some(44_100),
+            channels: Some(CHANNELS as u8),
+            samples: None,
+        };
+
+        let device = system.open_playback(None, &spec, |_| Mixer::new())?;
+        device.resume();
+
+        Ok(Self {
+            _system: system,
+            device,
+        })
+    }
+}
+
+impl AudioBackend for SdlBackend {
+    fn freq(&self) -> i32 {
+        self.device.spec().freq
+    }
+
+    fn lock(&mut self) -> Box<dyn DerefMut<Target = Mixer> + '_> {
+        Box::new(self.device.lock())
+    }
+
+    fn update(&mut self) {
+        self.device.resume();
+    }
+}
+
+/// Plays nothing and drops every sound it is given.
+///
+/// This is used when there is no audio device and in the editor.
+pub struct NullBackend {
+    mixer: Mixer,
+}
+
+impl NullBackend {
+    const FREQ: i32 = 44_100;
+
+    pub fn new() -> Self {
+        Self {
+            mixer: Mixer::new(),
+        }
+    }
+}
+
+impl AudioBackend for NullBackend {
+    fn freq(&self) -> i32 {
+        Self::FREQ
+    }
+
+    fn lock(&mut self) -> Box<dyn DerefMut<Target = Mixer> + '_> {
+        Box::new(&mut self.mixer)
+    }
+
+    fn update(&mut self) {
+        // Nothing ever plays the sounds, so they are dropped instead of piling
+        // up in the mixer.
+        self.mixer.clear();
+    }
+}
+
+/// A sound that was started while recording.
+#[cfg(test)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordedSound {
+    /// The number of frames that had passed when the sound was started.
+    pub tick: u64,
+    pub path: PathBuf,
+    pub options: PlayOptions,
+}
+
+/// Plays nothing, but remembers which sounds were started and when.
+///
+/// This is used in tests.
+#[cfg(test)]
+pub struct RecordingBackend {
+    mixer: Mixer,
+    tick: u64,
+    sounds: Arc<Mutex<Vec<RecordedSound>>>,
+}
+
+#[cfg(test)]
+impl RecordingBackend {
+    const FREQ: i32 = 44_100;
+
+    /// The number of frames the game is expected to run at pr. second.
+    ///
+    /// The mixer is run for this long every frame so that sounds still finish.
+    const FRAMES_PER_SECOND: i32 = 60;
+
+    pub fn new() -> Self {
+        Self {
+            mixer: Mixer::new(),
+            tick: 0,
+            sounds: Arc::default(),
+        }
+    }
+
+    /// Returns the sounds that have been started so far.
+    ///
+    /// The list keeps being updated after the backend has been given to an
+    /// audio manager.
+    pub fn sounds(&self) -> Arc<Mutex<Vec<RecordedSound>>> {
+        self.sounds.clone()
+    }
+}
+
+#[cfg(test)]
+impl AudioBackend for RecordingBackend {
+    fn freq(&self) -> i32 {
+        Self::FREQ
+    }
+
+    fn lock(&mut self) -> Box<dyn DerefMut<Target = Mixer> + '_> {
+        Box::new(&mut self.mixer)
+    }
+
+    fn update(&mut self) {
+        self.tick += 1;
+
+        let frames = (Self::FREQ / Self::FRAMES_PER_SECOND) as usize;
+        let mut output = vec![0.0; frames * CHANNELS];
+        self.mixer.callback(&mut output);
+    }
+
+    fn started(&mut self, path: &Path, options: &PlayOptions) {
+        let mut sounds = self.sounds.lock().unwrap();
+        sounds.push(RecordedSound {
+            tick: self.tick,
+            path: path.to_owned(),
+            options: *options,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::ThreadPool;
+
+    use super::*;
+    use crate::sound::*;
+
+    #[test]
+    fn test_recording_backend_records_started_sounds() {
+        let backend = RecordingBackend::new();
+        let sounds = backend.sounds();
+
+        let mut audio = AudioManager::new(backend, ThreadPool::new().unwrap());
+        audio.register(&Sound::JumpSmall).unwrap();
+        for _ in 0..12 {
+            audio.update();
+        }
+
+        audio.start(&Sound::JumpSmall);
+
+        let sounds = sounds.lock().unwrap();
+        assert_eq!(sounds.len(), 1);
+        assert_eq!(sounds[0].tick, 12);
+        assert!(sounds[0].path.ends_with("jump_small.wav"));
+    }
+}
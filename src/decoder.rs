@@ -0,0 +1,181 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::Path;
+use std::sync::Arc;
+
+use sdl2::audio::{AudioFormat, AudioSpecWAV};
+
+/// Audio that has been decoded but not yet converted to the format of the
+/// audio device.
+pub struct DecodedAudio {
+    pub format: AudioFormat,
+    pub channels: u8,
+    pub freq: i32,
+    /// The interleaved samples in `format`.
+    pub data: Vec<u8>,
+}
+
+impl DecodedAudio {
+    fn from_i16(samples: &[i16], channels: u8, freq: i32) -> Self {
+        Self {
+            format: AudioFormat::s16_sys(),
+            channels,
+            freq,
+            data: samples
+                .iter()
+                .flat_map(|sample| sample.to_ne_bytes())
+                .collect(),
+        }
+    }
+}
+
+/// Decodes a single audio file format.
+///
+/// Decoders are run on the thread pool, so they must be shareable between
+/// threads.
+pub trait Decoder: Send + Sync {
+    fn decode(&self, path: &Path) -> Result<DecodedAudio, String>;
+}
+
+/// Decodes WAV files with SDL.
+pub struct WavDecoder;
+
+impl Decoder for WavDecoder {
+    fn decode(&self, path: &Path) -> Result<DecodedAudio, String> {
+        let wav = AudioSpecWAV::load_wav(path)?;
+        Ok(DecodedAudio {
+            format: wav.format,
+            channels: wav.channels,
+            freq: wav.freq,
+            data: wav.buffer().to_vec(),
+        })
+    }
+}
+
+/// Decodes Ogg Vorbis files.
+pub struct OggDecoder;
+
+impl Decoder for OggDecoder {
+    fn decode(&self, path: &Path) -> Result<DecodedAudio, String> {
+        let file = File::open(path).map_err(|error| error.to_string())?;
+        let mut reader =
+            lewton::inside_ogg::OggStreamReader::new(file).map_err(|error| error.to_string())?;
+
+        let mut samples = Vec::new();
+        while let Some(packet) = reader
+            .read_dec_packet_itl()
+            .map_err(|error| error.to_string())?
+        {
+            samples.extend(packet);
+        }
+
+        let header = &reader.ident_hdr;
+        Ok(DecodedAudio::from_i16(
+            &samples,
+            header.audio_channels,
+            header.audio_sample_rate as i32,
+        ))
+    }
+}
+
+/// Decodes FLAC files.
+pub struct FlacDecoder;
+
+impl Decoder for FlacDecoder {
+    fn decode(&self, path: &Path) -> Result<DecodedAudio, String> {
+        let mut reader = claxon::FlacReader::open(path).map_err(|error| error.to_string())?;
+        let info = reader.streaminfo();
+
+        // FLAC can store up to 32 bits pr. sample, so the samples are scaled to
+        // floats instead of being truncated.
+        let scale = 1.0 / (1u64 << (info.bits_per_sample - 1)) as f32;
+        let mut data = Vec::new();
+        for sample in reader.samples() {
+            let sample = sample.map_err(|error| error.to_string())? as f32 * scale;
+            data.extend(sample.to_ne_bytes());
+        }
+
+        Ok(DecodedAudio {
+            format: AudioFormat::f32_sys(),
+            channels: info.channels as u8,
+            freq: info.sample_rate as i32,
+            data,
+        })
+    }
+}
+
+/// Decodes MP3 files.
+pub struct Mp3Decoder;
+
+impl Decoder for Mp3Decoder {
+    fn decode(&self, path: &Path) -> Result<DecodedAudio, String> {
+        let file = File::open(path).map_err(|error| error.to_string())?;
+        let mut decoder = minimp3::Decoder::new(file);
+
+        let mut samples = Vec::new();
+        let mut format = None;
+        loop {
+            match decoder.next_frame() {
+                Ok(frame) => {
+                    format.get_or_insert((frame.channels as u8, frame.sample_rate));
+                    samples.extend(frame.data);
+                }
+                Err(minimp3::Error::Eof) => break,
+                Err(minimp3::Error::SkippedData) => continue,
+                Err(error) => return Err(error.to_string()),
+            }
+        }
+
+        let (channels, freq) = format.ok_or("The file doesn't contain any MP3 frames")?;
+        Ok(DecodedAudio::from_i16(&samples, channels, freq))
+    }
+}
+
+/// The decoders that the audio manager knows about, by file extension.
+#[derive(Clone)]
+pub struct Decoders(HashMap<String, Arc<dyn Decoder>>);
+
+impl Decoders {
+    /// Adds `decoder` for the files that end with `extension`, replacing the
+    /// previous decoder for that extension.
+    ///
+    /// The extension is matched without regard to case.
+    pub fn insert(&mut self, extension: &str, decoder: impl Decoder + 'static) {
+        self.0.insert(extension.to_lowercase(), Arc::new(decoder));
+    }
+
+    /// Returns the decoder for the file in `path`, if the format is supported.
+    pub fn get(&self, path: &Path) -> Option<Arc<dyn Decoder>> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        self.0.get(&extension).cloned()
+    }
+}
+
+impl Default for Decoders {
+    fn default() -> Self {
+        let mut decoders = Self(HashMap::new());
+        decoders.insert("wav", WavDecoder);
+        decoders.insert("ogg", OggDecoder);
+        decoders.insert("flac", FlacDecoder);
+        decoders.insert("mp3", Mp3Decoder);
+        decoders
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decoder_is_chosen_by_extension() {
+        let decoders = Decoders::default();
+        assert!(decoders
+            .get(Path::new("assets/audio/sfx/jump_small.wav"))
+            .is_some());
+        assert!(decoders.get(Path::new("music/overworld.OGG")).is_some());
+        assert!(decoders
+            .get(Path::new("assets/levels/Level 1.level"))
+            .is_none());
+        assert!(decoders.get(Path::new("README")).is_none());
+    }
+}
@@ -1,22 +1,26 @@
 use std::cell::RefCell;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 
 use ::glam::*;
 use ::sdl2::event::*;
-use ::sdl2::keyboard::{KeyboardState, Keycode, Mod};
+use ::sdl2::keyboard::{KeyboardState, Keycode, Mod, Scancode};
 use ::sdl2::messagebox::*;
 use ::sdl2::mouse::{MouseButton, MouseState};
 use ::sdl2::pixels::Color;
 use ::sdl2::rect::{Point, Rect};
-use ::sdl2::render::*;
 use ::sdl2::video::*;
 use ::sdl2::{AudioSubsystem, VideoSubsystem};
-use ::windows::Win32::Foundation::*;
-use ::windows::Win32::System::Com::*;
-use ::windows::Win32::UI::Shell::*;
+use futures::executor::ThreadPool;
 
-use crate::level::read_level;
-use crate::os::{windows, MenuItem};
+use crate::assets::*;
+use crate::audio::AudioManager;
+use crate::audio_backend::*;
+use crate::level::*;
+use crate::map::*;
+use crate::os::*;
+use crate::renderer::Renderer;
+use crate::scene::*;
 
 const _SDL_WINDOW_INPUT_FOCUS: u32 = 0x00000200;
 const _SDL_WINDOW_MOUSE_FOCUS: u32 = 0x00000400;
@@ -33,20 +37,110 @@ use crate::Layer;
 //     RemoveMany { from: UVec2, to: Option<UVec2> },
 // }
 
+/// Something that can be done from the menu bar or with a shortcut.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EditorAction {
+    Open,
+    Save,
+    SaveAs,
+    Undo,
+    PreviousSegment,
+    NextSegment,
+    AddSegment,
+    NextLayer,
+    AddLayer,
+    Quit,
+}
+
+/// The level that is being edited and where in it tiles are placed.
 struct EditorState {
-    pub tiles: Vec<Option<(UVec2, Color)>>,
-    pub brush: Rc<RefCell<Color>>,
+    pub level: Level,
+    /// The index of the segment that is shown.
+    pub segment: usize,
+    /// The index of the layer in the segment that tiles are placed on.
+    pub layer: usize,
+    pub brush: Rc<RefCell<Block>>,
+}
+
+impl EditorState {
+    /// Starts editing `level` from its first segment.
+    ///
+    /// Returns whether a segment or layers had to be added to the level, which
+    /// then needs to be saved.
+    fn set_level(&mut self, mut level: Level) -> bool {
+        let mut changed = false;
+        if level.segments.is_empty() {
+            level.segments.push(Segment::new());
+            changed = true;
+        }
+
+        // Every segment needs a layer to place tiles on.
+        for segment in &mut level.segments {
+            if segment.layers.is_empty() {
+                segment.layers.push(TileLayer::solid(Vec::new()));
+                changed = true;
+            }
+        }
+
+        self.level = level;
+        self.segment = 0;
+        self.layer = 0;
+        changed
+    }
+
+    fn segment(&self) -> &Segment {
+        &self.level.segments[self.segment]
+    }
+
+    fn segment_mut(&mut self) -> &mut Segment {
+        &mut self.level.segments[self.segment]
+    }
+
+    /// Returns the tile at `coordinate` in the layer tiles are placed on.
+    fn tile(&self, coordinate: IVec2) -> Option<&MapTile> {
+        let tiles = &self.segment().layers[self.layer].tiles;
+        tiles.iter().find(|tile| tile.coordinate == coordinate)
+    }
+
+    fn tiles_mut(&mut self, segment: usize, layer: usize) -> &mut Vec<MapTile> {
+        &mut self.level.segments[segment].layers[layer].tiles
+    }
+}
+
+/// Removes the tile at `coordinate` from `tiles` and returns it.
+fn remove_tile(tiles: &mut Vec<MapTile>, coordinate: IVec2) -> Option<MapTile> {
+    let i = tiles
+        .iter()
+        .position(|tile| tile.coordinate == coordinate)?;
+    Some(tiles.remove(i))
 }
 
 pub struct Editor {
     _video: VideoSubsystem,
-    _audio: AudioSubsystem,
-    canvas: WindowCanvas,
+    _audio: Option<AudioSubsystem>,
+    renderer: Renderer,
+    assets: AssetManager,
+    /// Never plays anything, but the assets of the editor and its tools are
+    /// loaded with it.
+    audio_manager: AudioManager,
+    tiles: TileTable,
+    platform: Box<dyn Platform>,
+
+    /// The part of the segment that is shown. The size follows the window.
+    camera: Camera,
+    /// The file the level was opened from or last saved to.
+    path: Option<PathBuf>,
+    /// Closes the editor once the level has been saved, for when a new file
+    /// has to be picked before quitting.
+    close_after_save: bool,
+    /// The mouse button that is held down to place or remove tiles.
+    painting: Option<MouseButton>,
 
     should_close: Rc<RefCell<bool>>,
+    /// The actions picked from the menu bar since the last update.
+    actions: Rc<RefCell<Vec<EditorAction>>>,
 
     // Command stuff
-    mouse_clicks: Vec<MouseButton>,
     key_clicks: Vec<(Keycode, Mod)>,
     saved: bool,
 
@@ -63,72 +157,74 @@ trait Command: std::fmt::Debug {
     fn is_complete(&mut self) -> bool {
         true
     }
+
+    /// Whether the command changes the level, which then needs to be saved.
+    fn changes_level(&self) -> bool {
+        true
+    }
 }
 
 #[derive(Debug)]
-struct Insert(UVec2, Color);
+struct Insert {
+    segment: usize,
+    layer: usize,
+    tile: MapTile,
+    /// The tile that was painted over, if there was one.
+    replaced: Option<MapTile>,
+}
 
 impl Command for Insert {
     fn apply(&mut self, state: &mut EditorState) {
-        if let Some(tile) = state.tiles.iter_mut().find(|tile| tile.is_none()) {
-            *tile = Some((self.0, self.1));
-        } else {
-            state.tiles.push(Some((self.0, self.1)));
-        };
+        let tiles = state.tiles_mut(self.segment, self.layer);
+        self.replaced = remove_tile(tiles, self.tile.coordinate);
+        tiles.push(self.tile);
     }
 
     fn undo(&mut self, state: &mut EditorState) {
-        if let Some(tile) = state
-            .tiles
-            .iter_mut()
-            .find(|tile| matches!(tile, Some((tile, _)) if tile == &self.0))
-        {
-            *tile = None;
-        }
+        let tiles = state.tiles_mut(self.segment, self.layer);
+        remove_tile(tiles, self.tile.coordinate);
+        tiles.extend(self.replaced.take());
     }
 }
 
 #[derive(Debug)]
-struct Remove(UVec2, Color);
+struct Remove {
+    segment: usize,
+    layer: usize,
+    coordinate: IVec2,
+    removed: Option<MapTile>,
+}
 
 impl Command for Remove {
     fn apply(&mut self, state: &mut EditorState) {
-        if let Some(tile) = state
-            .tiles
-            .iter_mut()
-            .find(|tile| tile.map_or(false, |tile| tile.0 == self.0))
-        {
-            if let Some(tile) = tile.take() {
-                self.1 = tile.1;
-            }
-        }
+        let tiles = state.tiles_mut(self.segment, self.layer);
+        self.removed = remove_tile(tiles, self.coordinate);
     }
 
     fn undo(&mut self, state: &mut EditorState) {
-        if let Some(tile) = state.tiles.iter_mut().find(|tile| tile.is_none()) {
-            *tile = Some((self.0, self.1))
-        } else {
-            state.tiles.push(Some((self.0, self.1)))
-        };
+        let tiles = state.tiles_mut(self.segment, self.layer);
+        tiles.extend(self.removed.take());
     }
 }
 
 #[derive(Debug)]
 struct Pick {
-    new: Color,
-    old: Color,
+    new: Block,
+    old: Block,
 }
 
 impl Command for Pick {
     fn apply(&mut self, state: &mut EditorState) {
         *state.brush.borrow_mut() = self.new;
-        println!("yeet");
-        println!("{:?}", *RefCell::borrow(&state.brush));
     }
 
     fn undo(&mut self, state: &mut EditorState) {
         *state.brush.borrow_mut() = self.old;
     }
+
+    fn changes_level(&self) -> bool {
+        false
+    }
 }
 
 enum SaveButton {
@@ -138,42 +234,78 @@ enum SaveButton {
 }
 
 impl Editor {
-    const TILES: u32 = 16;
-
-    pub fn new(video: VideoSubsystem, audio: AudioSubsystem) -> Self {
-        // let window = video.window("Editor", 1600, 800).build().unwrap();
-        let mut window = windows::Window::new("Editor");
-
-        let menu = windows::new_menu(vec![
+    const LEVEL_PATH: &str = "./assets/levels/";
+    /// How many pixels on the screen each pixel of the segment takes up.
+    const ZOOM: f32 = 2.0;
+    /// How far the arrow keys move the camera each frame.
+    const PAN_SPEED: f32 = 4.0;
+
+    pub fn new(video: VideoSubsystem, audio: Option<AudioSubsystem>) -> Self {
+        let actions = Rc::new(RefCell::new(Vec::new()));
+        let item = |title, action| {
+            let actions = Rc::clone(&actions);
             MenuItem::Action {
-                title: "Mario",
-                action: Box::new(|| {}),
+                title,
+                action: Box::new(move || actions.borrow_mut().push(action)),
+            }
+        };
+
+        let menu = vec![
+            MenuItem::SubMenu {
+                title: "File",
+                items: vec![
+                    item("Open", EditorAction::Open),
+                    item("Save", EditorAction::Save),
+                    item("Save As", EditorAction::SaveAs),
+                    MenuItem::Divider,
+                    item("Quit", EditorAction::Quit),
+                ],
             },
-            MenuItem::Action {
-                title: "Luigi",
-                action: Box::new(|| {}),
+            MenuItem::SubMenu {
+                title: "Edit",
+                items: vec![item("Undo", EditorAction::Undo)],
+            },
+            MenuItem::SubMenu {
+                title: "Segment",
+                items: vec![
+                    item("Previous Segment", EditorAction::PreviousSegment),
+                    item("Next Segment", EditorAction::NextSegment),
+                    item("Add Segment", EditorAction::AddSegment),
+                    MenuItem::Divider,
+                    item("Next Layer", EditorAction::NextLayer),
+                    item("Add Layer", EditorAction::AddLayer),
+                ],
             },
-        ]);
+        ];
 
-        window.set_menu(&menu);
+        let (platform, window) = new_window(&video, "Editor", uvec2(1600, 800), menu);
+        let canvas = window.into_canvas().accelerated().build().unwrap();
 
-        let mut canvas = window
-            .into_sdl2(video.clone())
-            .into_canvas()
-            .accelerated()
-            .build()
-            .unwrap();
+        let audio_manager = AudioManager::new(NullBackend::new(), ThreadPool::new().unwrap());
+        let assets = AssetManager::new(canvas.texture_creator(), audio_manager.clone());
+        let mut renderer = Renderer::new(canvas, assets.clone());
 
-        canvas.window_mut().show();
-        canvas.window_mut().set_size(1600, 800).unwrap();
+        // The segment is drawn at a fixed zoom instead of being fit to the window
+        // like in the game.
+        renderer.canvas.set_logical_size(0, 0).unwrap();
 
-        Self {
+        let mut editor = Self {
             _video: video,
             _audio: audio,
-            canvas,
+            renderer,
+            assets,
+            audio_manager,
+            tiles: TileTable::load(&TileTable::PATH).unwrap(),
+            platform,
+
+            camera: Camera::new(Vec2::ZERO),
+            path: None,
+            close_after_save: false,
+            painting: None,
+
             should_close: Rc::new(RefCell::new(false)),
+            actions,
 
-            mouse_clicks: Vec::with_capacity(4),
             key_clicks: Vec::with_capacity(16),
             saved: false,
 
@@ -181,10 +313,15 @@ impl Editor {
             commands: Rc::new(RefCell::new(Vec::with_capacity(64))),
 
             state: EditorState {
-                tiles: Vec::with_capacity(16 * 32),
-                brush: Rc::new(RefCell::new(Color::RED)),
+                level: Level::new("Untitled"),
+                segment: 0,
+                layer: 0,
+                brush: Rc::new(RefCell::new(Block::Ground)),
             },
-        }
+        };
+
+        editor.reset_camera();
+        editor
     }
 
     fn show_save_message_box(&self) -> SaveButton {
@@ -222,167 +359,397 @@ impl Editor {
         }
     }
 
-    fn handle_open_level(&mut self) {
-        let dialog: IFileOpenDialog =
-            unsafe { CoCreateInstance(&FileOpenDialog, None, CLSCTX_INPROC_SERVER).unwrap() };
+    fn handle_open_level(&mut self, path: &Path) {
+        let level = match read_level(&path) {
+            Ok(level) => level,
+            Err(error) => {
+                eprintln!("The level {} could not be opened: {error}", path.display());
+                return;
+            }
+        };
 
-        if unsafe { dialog.Show(HWND::default()).is_ok() } {
-            let path = unsafe {
-                let item = dialog.GetResult().unwrap();
-                let path = item.GetDisplayName(SIGDN_FILESYSPATH).unwrap();
-                path.to_string().unwrap()
-            };
+        let changed = self.state.set_level(level);
+        self.path = Some(path.to_owned());
+        self.commands.borrow_mut().clear();
+        self.ncommands = 0;
+        self.saved = !changed;
+        self.reset_camera();
+    }
 
-            let level = read_level(&path).unwrap();
-            println!("{level:?}");
+    /// Writes the level to the file it was opened from, or asks for a file when
+    /// it hasn't been saved before.
+    fn save(&mut self) {
+        match self.path.clone() {
+            Some(path) => self.write_level(&path),
+            None => self.apply_action(EditorAction::SaveAs),
+        }
+    }
+
+    fn write_level(&mut self, path: &Path) {
+        // A new level is named after the file it's first saved to.
+        if self.path.is_none()
+            && let Some(name) = path.file_stem().and_then(|name| name.to_str())
+        {
+            self.state.level.name = name.to_owned();
+        }
 
-            self.canvas.window_mut().set_title(&format!(
-                "Editor | Name: {} | Segment {}/{} | Path: {path}",
-                level.name,
-                1,
-                level.segments.len()
-            ));
+        if let Err(error) = write_level(&path, &self.state.level) {
+            eprintln!(
+                "The level could not be saved to {}: {error}",
+                path.display()
+            );
+            return;
+        }
+
+        self.path = Some(path.to_owned());
+        self.saved = true;
+
+        if self.close_after_save {
+            *self.should_close.borrow_mut() = true;
         }
     }
 
-    fn render(&mut self) {
-        let Self { canvas, .. } = self;
-        let (width, height) = canvas.window().size();
-        let size = height / Self::TILES;
+    /// Closes the editor, asking to save first when there are unsaved changes.
+    fn quit(&mut self) {
+        if self.saved {
+            *self.should_close.borrow_mut() = true;
+            return;
+        }
+
+        match self.show_save_message_box() {
+            SaveButton::Save => {
+                self.close_after_save = true;
+                self.save();
+            }
+            SaveButton::Discard => *self.should_close.borrow_mut() = true,
+            SaveButton::Cancel => {}
+        }
+    }
 
-        // Background Color
-        canvas.set_draw_color(Color::RGB(229, 231, 235));
-        canvas.fill_rect(None).unwrap();
+    fn apply_action(&mut self, action: EditorAction) {
+        let directory = Path::new(Self::LEVEL_PATH);
 
-        // Background Grid
-        {
-            canvas.set_draw_color(Color::RGB(156, 163, 175));
-            for i in 0..(width / size) {
-                let _ = canvas.draw_line(
-                    Point::new((i * size + size) as _, 0),
-                    Point::new((i * size + size) as _, height as _),
-                );
+        match action {
+            EditorAction::Open => {
+                self.platform
+                    .show_file_dialog(FileDialog::Open, directory, "level");
+            }
+            EditorAction::Save => self.save(),
+            EditorAction::SaveAs => {
+                self.platform
+                    .show_file_dialog(FileDialog::Save, directory, "level");
             }
+            EditorAction::Undo => {
+                // Commands from this frame haven't been applied yet, and undoing
+                // one before it has been would undo something else.
+                self.apply_new_commands();
+                if let Some(mut command) = self.commands.borrow_mut().pop() {
+                    command.undo(&mut self.state);
+                    self.saved &= !command.changes_level();
+                }
 
-            for i in 0..Self::TILES {
-                let _ = canvas.draw_line(
-                    Point::new(0, (i * size + size) as _),
-                    Point::new(width as _, (i * size + size) as _),
-                );
+                self.ncommands = RefCell::borrow(&self.commands).len();
+            }
+            EditorAction::PreviousSegment => {
+                self.select_segment(self.state.segment.saturating_sub(1));
+            }
+            EditorAction::NextSegment => self.select_segment(self.state.segment + 1),
+            EditorAction::AddSegment => {
+                self.state.level.segments.push(Segment::new());
+                self.select_segment(self.state.level.segments.len() - 1);
+                self.saved = false;
+            }
+            EditorAction::NextLayer => {
+                let layers = self.state.segment().layers.len();
+                self.state.layer = (self.state.layer + 1) % layers;
             }
+            EditorAction::AddLayer => {
+                let layers = &mut self.state.segment_mut().layers;
+                layers.push(TileLayer::solid(Vec::new()));
+                self.state.layer = layers.len() - 1;
+                self.saved = false;
+            }
+            EditorAction::Quit => self.quit(),
+        }
+    }
+
+    /// Applies the commands that have been added since the last time.
+    fn apply_new_commands(&mut self) {
+        let mut commands = self.commands.borrow_mut();
+        if let Some(new) = commands.get_mut(self.ncommands..) {
+            for command in new {
+                if command.is_complete() {
+                    command.apply(&mut self.state);
+                    self.saved &= !command.changes_level();
+                }
+            }
+        }
+
+        self.ncommands = commands.len();
+    }
+
+    /// Shows the segment at `index`, or the last one if there is no such
+    /// segment.
+    fn select_segment(&mut self, index: usize) {
+        let last = self.state.level.segments.len() - 1;
+        self.state.segment = index.min(last);
+        self.state.layer = 0;
+        self.reset_camera();
+    }
+
+    /// Moves the camera back to the origin of the segment, just below the menu
+    /// bar.
+    fn reset_camera(&mut self) {
+        let menu_height = self.platform.menu_height() as f32;
+        self.camera.position = vec2(0.0, -menu_height / Self::ZOOM);
+    }
+
+    /// Shows the name of the level and what is being edited in the title bar.
+    fn update_title(&mut self) {
+        let path = match &self.path {
+            Some(path) => path.display().to_string(),
+            None => String::from("Not saved"),
+        };
+
+        let title = format!(
+            "Editor | Name: {}{} | Segment {}/{} | Layer {}/{} | Path: {}",
+            self.state.level.name,
+            if self.saved { "" } else { "*" },
+            self.state.segment + 1,
+            self.state.level.segments.len(),
+            self.state.layer + 1,
+            self.state.segment().layers.len(),
+            path
+        );
+
+        let window = self.renderer.canvas.window_mut();
+        if window.title() != title {
+            let _ = window.set_title(&title);
+        }
+    }
+
+    /// Returns the coordinate of the tile at `position` in the window, unless
+    /// the position is covered by the menu bar.
+    fn tile_at(&self, position: IVec2) -> Option<IVec2> {
+        if position.y < self.platform.menu_height() as i32 {
+            return None;
+        }
+
+        let world = self.camera.position + position.as_vec2() / Self::ZOOM;
+        Some((world / Renderer::TILE_SIZE as f32).floor().as_ivec2())
+    }
+
+    /// Places the brush at `coordinate` in the current layer.
+    fn paint(&mut self, coordinate: IVec2) {
+        let block = *RefCell::borrow(&self.state.brush);
+        if self.state.tile(coordinate).map(|tile| tile.block) == Some(block) {
+            return;
         }
 
-        self.render_state();
-        self.canvas.present();
+        self.commands.borrow_mut().push(Box::new(Insert {
+            segment: self.state.segment,
+            layer: self.state.layer,
+            tile: MapTile {
+                block,
+                coordinate,
+                revealed: false,
+            },
+            replaced: None,
+        }));
     }
 
-    fn render_state(&mut self) {
-        let Self { canvas, state, .. } = self;
-        let (_, height) = canvas.window().size();
-        let size = height / Self::TILES;
+    /// Removes the tile at `coordinate` from the current layer.
+    fn erase(&mut self, coordinate: IVec2) {
+        if self.state.tile(coordinate).is_none() {
+            return;
+        }
+
+        self.commands.borrow_mut().push(Box::new(Remove {
+            segment: self.state.segment,
+            layer: self.state.layer,
+            coordinate,
+            removed: None,
+        }));
+    }
+
+    /// Builds the scene the game would show for the current segment, with the
+    /// player standing at the spawn.
+    fn scene(&self) -> Scene {
+        let segment = self.state.segment();
+
+        Scene {
+            camera: self.camera.clone(),
+            entities: segment.entities.clone(),
+            enemies: segment.spawn_enemies(),
+            player: Player::new(segment.spawn.unwrap_or(Vec2::ZERO)),
+            text: Vec::new(),
+            layers: segment.layers.clone(),
+            background: segment.background,
+            backgrounds: segment.backgrounds.clone(),
+            player_behind: false,
+            cover: None,
+        }
+    }
+
+    fn render(&mut self, hovered: Option<IVec2>) {
+        let (width, height) = self.renderer.canvas.window().size();
+        self.camera.size = vec2(width as f32, height as f32) / Self::ZOOM;
+
+        // The segment is drawn by the same renderer as in the game, so it looks
+        // exactly like it will when it's played.
+        let mut scene = self.scene();
+        self.renderer
+            .canvas
+            .set_scale(Self::ZOOM, Self::ZOOM)
+            .unwrap();
+        self.renderer.draw_scene(&mut scene);
+        self.renderer.canvas.set_scale(1.0, 1.0).unwrap();
+
+        self.render_overlay(hovered);
+        self.platform.draw(&mut self.renderer.canvas);
+        self.renderer.present();
+    }
+
+    /// Draws the grid and the outlines that only the editor shows.
+    fn render_overlay(&mut self, hovered: Option<IVec2>) {
+        let Self {
+            renderer,
+            camera,
+            state,
+            tiles,
+            ..
+        } = self;
+
+        let canvas = &mut renderer.canvas;
+        let (width, height) = canvas.window().size();
+        let tile_size = Renderer::TILE_SIZE as f32;
+
+        // Where the top-left corner of the tile at `coordinate` is in the window.
+        let to_window = |coordinate: IVec2| {
+            let position = coordinate.as_vec2() * tile_size - camera.position;
+            (position * Self::ZOOM).floor().as_ivec2()
+        };
+
+        let outline = |coordinate: IVec2| {
+            let position = to_window(coordinate);
+            let size = (tile_size * Self::ZOOM) as u32;
+            Rect::new(position.x, position.y, size, size)
+        };
+
+        // Background Grid
+        {
+            let first = (camera.position / tile_size).floor().as_ivec2();
+            let last = ((camera.position + camera.size) / tile_size)
+                .ceil()
+                .as_ivec2();
+
+            canvas.set_draw_color(Color::RGBA(0, 0, 0, 40));
+            for x in first.x..=last.x {
+                let x = to_window(ivec2(x, 0)).x;
+                let _ = canvas.draw_line(Point::new(x, 0), Point::new(x, height as _));
+            }
+
+            for y in first.y..=last.y {
+                let y = to_window(ivec2(0, y)).y;
+                let _ = canvas.draw_line(Point::new(0, y), Point::new(width as _, y));
+            }
+        }
 
-        // canvas.set_draw_color(Color::RGB(255, 0, 0));
-        for (tile, color) in state.tiles.iter().flatten() {
-            let rect = Rect::new((tile.x * size) as _, (tile.y * size) as _, size, size);
+        // Hidden tiles aren't drawn until they are revealed in the game, so they
+        // are outlined to show where they are.
+        canvas.set_draw_color(Color::RGBA(255, 255, 255, 160));
+        let layers = &state.segment().layers;
+        for tile in layers.iter().flat_map(|layer| &layer.tiles) {
+            if !tiles.is_visible(tile) {
+                let _ = canvas.draw_rect(outline(tile.coordinate));
+            }
+        }
 
-            canvas.set_draw_color(*color);
-            let _ = canvas.fill_rect(rect);
+        if let Some(coordinate) = hovered {
+            canvas.set_draw_color(Color::WHITE);
+            let _ = canvas.draw_rect(outline(coordinate));
         }
     }
 }
 
 impl Layer for Editor {
     fn update(&mut self, keyboard: KeyboardState, mouse: MouseState) {
-        let Self { canvas, .. } = self;
-        let (_, height) = canvas.window().size();
-        let size = height / Self::TILES;
-
-        for mouse_button in &self.mouse_clicks {
-            let Self { state, .. } = self;
-
-            match mouse_button {
-                MouseButton::Left => {
-                    // println!("{:?}", *RefCell::borrow(&state.brush));
-                    self.commands.borrow_mut().push(Box::new(Insert(
-                        uvec2(mouse.x() as u32 / size, mouse.y() as u32 / size),
-                        *RefCell::borrow(&state.brush),
-                    )));
-                }
-                MouseButton::Right => {
-                    let position = uvec2(mouse.x() as u32 / size, mouse.y() as u32 / size);
-                    if state
-                        .tiles
-                        .iter()
-                        .any(|tile| matches!(tile, Some((tile, ..)) if tile == &position))
-                    {
-                        self.commands
-                            .borrow_mut()
-                            .push(Box::new(Remove(position, Color::BLACK)));
-                    }
-                }
-                _ => {}
-            }
+        self.assets.update();
+        self.platform.update();
+
+        let actions = std::mem::take(&mut *self.actions.borrow_mut());
+        for action in actions {
+            self.apply_action(action);
         }
 
-        for (keycode, keymod) in self.key_clicks.clone() {
-            match (keycode, keymod) {
-                (Keycode::Z, _) => {
-                    if let Some(mut command) = self.commands.borrow_mut().pop() {
-                        command.undo(&mut self.state);
-                    }
-                }
-                (Keycode::O, Mod::LCTRLMOD) => {
-                    self.handle_open_level();
-                }
-                (Keycode::S, Mod::LCTRLMOD) => {
-                    // Save a level
-                }
-                (Keycode::AcForward, Mod::LCTRLMOD) => {
-                    // Next Segment
-                }
-                (Keycode::AcBack, Mod::LCTRLMOD) => {
-                    // Previous Segment
-                }
+        match self.platform.picked_file() {
+            Some((FileDialog::Open, path)) => self.handle_open_level(&path),
+            Some((FileDialog::Save, path)) => self.write_level(&path),
+            // The dialog was cancelled, so there's nothing to close after.
+            None if !self.platform.is_dialog_open() => self.close_after_save = false,
+            None => {}
+        }
+
+        // Input is meant for the dialog while one is open.
+        if self.platform.is_dialog_open() {
+            self.painting = None;
+            self.key_clicks.clear();
+        } else {
+            let pressed = |scancode| keyboard.is_scancode_pressed(scancode) as i32 as f32;
+            let direction = vec2(
+                pressed(Scancode::Right) - pressed(Scancode::Left),
+                pressed(Scancode::Down) - pressed(Scancode::Up),
+            );
+            self.camera.position += direction * Self::PAN_SPEED;
+        }
+
+        let tile = self.tile_at(ivec2(mouse.x(), mouse.y()));
+        if let Some(coordinate) = tile {
+            match self.painting {
+                Some(MouseButton::Left) => self.paint(coordinate),
+                Some(MouseButton::Right) => self.erase(coordinate),
                 _ => {}
             }
         }
 
-        // We should always apply the latest changes
-        // println!("{:?}", RefCell::borrow(&self.commands));
-        if self.ncommands < RefCell::borrow(&self.commands).len() {
-            let Self { state, .. } = self;
+        for (keycode, keymod) in self.key_clicks.clone() {
+            let ctrl = keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD);
+            let action = match keycode {
+                Keycode::Z if ctrl => EditorAction::Undo,
+                Keycode::O if ctrl => EditorAction::Open,
+                Keycode::S if ctrl => EditorAction::Save,
+                Keycode::PageUp => EditorAction::PreviousSegment,
+                Keycode::PageDown => EditorAction::NextSegment,
+                Keycode::Tab => EditorAction::NextLayer,
+                _ => continue,
+            };
 
-            let commands = &mut self.commands.borrow_mut();
-            for command in commands[self.ncommands..].iter_mut() {
-                if command.is_complete() {
-                    command.apply(state)
-                }
-            }
+            self.apply_action(action);
         }
 
-        self.ncommands = RefCell::borrow(&self.commands).len();
-        self.render();
+        // We should always apply the latest changes
+        self.apply_new_commands();
+        self.update_title();
+        self.render(tile);
 
-        self.mouse_clicks.clear();
         self.key_clicks.clear();
     }
 
     fn handle_events(&mut self, events: &mut dyn Iterator<Item = &Event>) {
         for event in events {
+            // The menu bar and dialogs get to handle events before the editor.
+            if self.platform.handle_event(event) {
+                continue;
+            }
+
             match event {
                 Event::Window { win_event, .. } if *win_event == WindowEvent::Close => {
-                    if !self.saved {
-                        match self.show_save_message_box() {
-                            SaveButton::Save => {
-                                // TODO: Save skrrt skrrrt
-                                *self.should_close.borrow_mut() = true;
-                            }
-                            SaveButton::Discard => *self.should_close.borrow_mut() = true,
-                            _ => {}
-                        }
-                    }
+                    self.quit();
+                }
+                Event::MouseButtonDown { mouse_btn, .. } => self.painting = Some(*mouse_btn),
+                Event::MouseButtonUp { mouse_btn, .. } if self.painting == Some(*mouse_btn) => {
+                    self.painting = None
                 }
-                Event::MouseButtonDown { mouse_btn, .. } => self.mouse_clicks.push(*mouse_btn),
                 Event::KeyDown {
                     keycode,
                     keymod,
@@ -401,35 +768,40 @@ impl Layer for Editor {
     }
 
     fn window(&self) -> &Window {
-        self.canvas.window()
+        self.renderer.canvas.window()
     }
 
     fn window_mut(&mut self) -> &mut Window {
-        self.canvas.window_mut()
+        self.renderer.canvas.window_mut()
     }
 }
 
 pub struct EditorTools {
     video: VideoSubsystem,
-    audio: AudioSubsystem,
-
-    canvas: WindowCanvas,
+    audio: Option<AudioSubsystem>,
 
-    colors: Vec<Color>,
+    renderer: Renderer,
+    assets: AssetManager,
+    tiles: TileTable,
+    /// The blocks that can be picked, in the order they are shown.
+    blocks: Vec<Block>,
 
     commands: Rc<RefCell<Vec<Box<dyn Command>>>>,
     should_close: Rc<RefCell<bool>>,
-    brush: Rc<RefCell<Color>>,
+    brush: Rc<RefCell<Block>>,
 }
 
 impl EditorTools {
     const WINDOW_WIDTH: u32 = 250;
     const WINDOW_HEIGHT: u32 = 1000;
 
-    const TILES: u32 = 5;
-    const TILE_SIZE: u32 = 250 / Self::TILES;
+    const ZOOM: u32 = 3;
+    /// The space each block takes up in the palette, in the pixels of the
+    /// tilesheet.
+    const SLOT_SIZE: u32 = Renderer::TILE_SIZE + 4;
+    const COLUMNS: u32 = Self::WINDOW_WIDTH / (Self::SLOT_SIZE * Self::ZOOM);
 
-    pub fn new(editor: &Editor, video: VideoSubsystem, audio: AudioSubsystem) -> Self {
+    pub fn new(editor: &Editor, video: VideoSubsystem, audio: Option<AudioSubsystem>) -> Self {
         let window = video
             .window("Tile Picker", Self::WINDOW_WIDTH, Self::WINDOW_HEIGHT)
             .build()
@@ -437,38 +809,76 @@ impl EditorTools {
 
         let canvas = window.into_canvas().build().unwrap();
 
+        // Textures belong to the window they were created for, so the palette
+        // loads the tilesheet again.
+        let assets = AssetManager::new(canvas.texture_creator(), editor.audio_manager.clone());
+        let mut renderer = Renderer::new(canvas, assets.clone());
+        renderer.canvas.set_logical_size(0, 0).unwrap();
+
+        let tiles = editor.tiles.clone();
+        let blocks = tiles.blocks();
+
         Self {
             video,
             audio,
-            canvas,
-
-            colors: vec![Color::RED, Color::GREEN, Color::BLUE],
+            renderer,
+            assets,
+            tiles,
+            blocks,
 
             commands: Rc::clone(&editor.commands),
             should_close: Rc::clone(&editor.should_close),
             brush: Rc::clone(&editor.state.brush),
         }
     }
+
+    /// Returns the top-left corner of the slot at `index` in the pixels of the
+    /// tilesheet.
+    fn slot(index: usize) -> IVec2 {
+        let columns = Self::COLUMNS as usize;
+        let slot = ivec2((index % columns) as i32, (index / columns) as i32);
+        slot * Self::SLOT_SIZE as i32
+    }
 }
 
 impl Layer for EditorTools {
     fn update(&mut self, keyboard: KeyboardState, mouse: MouseState) {
-        let Self { canvas, .. } = self;
-
-        for (i, color) in self.colors.iter().enumerate() {
-            canvas.set_draw_color(*color);
+        self.assets.update();
+
+        let Self {
+            renderer,
+            tiles,
+            blocks,
+            brush,
+            ..
+        } = self;
+
+        let zoom = Self::ZOOM as f32;
+        let size = uvec2(Self::WINDOW_WIDTH, Self::WINDOW_HEIGHT);
+        let camera = Camera::new(size.as_vec2() / zoom);
+
+        renderer.canvas.set_draw_color(Color::RGB(229, 231, 235));
+        renderer.canvas.clear();
+
+        renderer.canvas.set_scale(zoom, zoom).unwrap();
+        for (i, block) in blocks.iter().enumerate() {
+            let position = Self::slot(i) + 2;
+            renderer.draw_image(&camera, &tiles.sprite(block), position.as_vec2(), 1);
+        }
+        renderer.canvas.set_scale(1.0, 1.0).unwrap();
 
-            let tile = Rect::new(
-                (i % 5 * Self::TILE_SIZE as usize) as _,
-                (i / 5 * Self::TILE_SIZE as usize) as _,
-                Self::TILE_SIZE as _,
-                Self::TILE_SIZE as _,
-            );
+        let brush = *RefCell::borrow(brush);
+        if let Some(i) = blocks.iter().position(|block| *block == brush) {
+            let position = Self::slot(i) * Self::ZOOM as i32;
+            let size = Self::SLOT_SIZE * Self::ZOOM;
 
-            canvas.fill_rect(tile).unwrap();
+            renderer.canvas.set_draw_color(Color::RED);
+            let _ = renderer
+                .canvas
+                .draw_rect(Rect::new(position.x, position.y, size, size));
         }
 
-        canvas.present();
+        renderer.present();
     }
 
     fn handle_events(&mut self, events: &mut dyn Iterator<Item = &Event>) {
@@ -477,19 +887,18 @@ impl Layer for EditorTools {
                 Event::MouseButtonDown {
                     mouse_btn, x, y, ..
                 } if mouse_btn == &MouseButton::Left => {
-                    let ix = x / (Self::TILE_SIZE as i32);
-                    let iy = y / (Self::TILE_SIZE as i32);
-                    let i = ix + (iy * Self::TILES as i32);
+                    let slot = ivec2(*x, *y) / (Self::SLOT_SIZE * Self::ZOOM) as i32;
+                    if slot.x < 0 || slot.y < 0 || slot.x >= Self::COLUMNS as i32 {
+                        continue;
+                    }
 
-                    if let Some(brush) = self.colors.get(i as usize) {
+                    let i = slot.x + slot.y * Self::COLUMNS as i32;
+                    if let Some(brush) = self.blocks.get(i as usize) {
                         let old = *RefCell::borrow(&self.brush);
                         if old.ne(brush) {
-                            // println!("{brush:?}");
-
                             self.commands
                                 .borrow_mut()
                                 .push(Box::new(Pick { new: *brush, old }));
-                            println!("{:?}", self.commands.borrow_mut());
                         }
                     }
                 }
@@ -503,10 +912,127 @@ impl Layer for EditorTools {
     }
 
     fn window(&self) -> &Window {
-        self.canvas.window()
+        self.renderer.canvas.window()
     }
 
     fn window_mut(&mut self) -> &mut Window {
-        self.canvas.window_mut()
+        self.renderer.canvas.window_mut()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state() -> EditorState {
+        EditorState {
+            level: Level::new("test"),
+            segment: 0,
+            layer: 0,
+            brush: Rc::new(RefCell::new(Block::Ground)),
+        }
+    }
+
+    fn tile(block: Block, coordinate: IVec2) -> MapTile {
+        MapTile {
+            block,
+            coordinate,
+            revealed: false,
+        }
+    }
+
+    fn insert(tile: MapTile) -> Insert {
+        Insert {
+            segment: 0,
+            layer: 0,
+            tile,
+            replaced: None,
+        }
+    }
+
+    #[test]
+    fn test_insert_can_be_undone() {
+        let mut state = state();
+        let mut command = insert(tile(Block::Ground, ivec2(1, 2)));
+
+        command.apply(&mut state);
+        assert_eq!(
+            state.tile(ivec2(1, 2)),
+            Some(&tile(Block::Ground, ivec2(1, 2)))
+        );
+
+        command.undo(&mut state);
+        assert_eq!(state.tile(ivec2(1, 2)), None);
+    }
+
+    #[test]
+    fn test_insert_restores_the_replaced_tile() {
+        let mut state = state();
+        let mut first = insert(tile(Block::Ground, ivec2(1, 2)));
+        let mut second = insert(tile(Block::Stone, ivec2(1, 2)));
+
+        first.apply(&mut state);
+        second.apply(&mut state);
+        assert_eq!(
+            state.segment().layers[0].tiles,
+            [tile(Block::Stone, ivec2(1, 2))]
+        );
+
+        second.undo(&mut state);
+        assert_eq!(
+            state.segment().layers[0].tiles,
+            [tile(Block::Ground, ivec2(1, 2))]
+        );
+    }
+
+    #[test]
+    fn test_remove_can_be_undone() {
+        let mut state = state();
+        insert(tile(Block::Wall, ivec2(3, 4))).apply(&mut state);
+
+        let mut command = Remove {
+            segment: 0,
+            layer: 0,
+            coordinate: ivec2(3, 4),
+            removed: None,
+        };
+
+        command.apply(&mut state);
+        assert_eq!(state.tile(ivec2(3, 4)), None);
+
+        command.undo(&mut state);
+        assert_eq!(
+            state.tile(ivec2(3, 4)),
+            Some(&tile(Block::Wall, ivec2(3, 4)))
+        );
+
+        // Removing where there is no tile does nothing, and neither does
+        // undoing it.
+        let mut command = Remove {
+            segment: 0,
+            layer: 0,
+            coordinate: ivec2(0, 0),
+            removed: None,
+        };
+
+        command.apply(&mut state);
+        command.undo(&mut state);
+        assert_eq!(state.segment().layers[0].tiles.len(), 1);
+    }
+
+    #[test]
+    fn test_levels_without_layers_are_changed() {
+        let mut state = state();
+        assert!(!state.set_level(Level::new("test")));
+
+        let mut level = Level::new("test");
+        level.segments[0].layers.clear();
+        assert!(state.set_level(level));
+        assert_eq!(state.segment().layers.len(), 1);
+
+        let mut level = Level::new("test");
+        level.segments.clear();
+        assert!(state.set_level(level));
+        assert_eq!(state.level.segments.len(), 1);
     }
 }
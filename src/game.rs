@@ -1,7 +1,7 @@
-use std::cell::RefCell;
 use std::fs::*;
 use std::future::join;
 use std::path::{Path, PathBuf};
+use std::rc::Rc;
 use std::time::{Instant, Duration};
 
 use ::glam::*;
@@ -12,16 +12,20 @@ use ::serde_json as json;
 use futures::executor::ThreadPool;
 use futures::task::{Spawn, SpawnExt};
 
+use crate::assets::*;
 use crate::audio::*;
 use crate::level::*;
 use crate::map::*;
 use crate::renderer::Renderer;
 use crate::scene::*;
+use crate::sound::*;
+use crate::transition::*;
 
 const GRAVITY: f32 = 9.82 * 0.1;
 
 pub struct GameSystems {
     pub audio: AudioManager,
+    pub assets: AssetManager,
     pub thread_pool: ThreadPool,
 }
 
@@ -29,13 +33,13 @@ pub struct GameSystems {
 struct LevelManager {
     levels: Vec<PathBuf>,
     level_names: Vec<String>,
-    current: Option<Level>,
+    current: Option<Handle<Level>>,
+    current_name: Option<String>,
 }
 
 impl LevelManager {
     pub fn new(path: &impl AsRef<Path>) -> Self {
         let mut levels = Vec::new();
-        let mut level_names = Vec::new();
 
         let directory = read_dir(path).unwrap();
         for entry in directory.flatten() {
@@ -46,19 +50,32 @@ impl LevelManager {
                 let file_name = path.file_name().unwrap();
                 let file_name = file_name.to_str().unwrap().strip_suffix(".level").unwrap();
 
-                level_names.push(file_name.to_owned());
-                levels.push(path);
+                // Levels that can't be started, like the ones that are still being made in
+                // the editor, aren't listed.
+                match read_level(&path) {
+                    Ok(level) if level.start.is_some_and(|start| start < level.segments.len()) => {
+                        levels.push((file_name.to_owned(), path));
+                    }
+                    Ok(_) => eprintln!("Skipped the level {file_name} since it has no segment to start in"),
+                    Err(error) => eprintln!("Skipped the level {file_name} since it could not be read: {error}"),
+                }
             }
         }
 
+        // The order of the entries in a directory is platform dependent, so we sort
+        // them to make sure that the levels are always listed in the same order.
+        levels.sort();
+        let (level_names, levels) = levels.into_iter().unzip();
+
         Self {
             levels,
             level_names,
             current: None,
+            current_name: None,
         }
     }
 
-    pub fn load(&mut self, name: &str) -> &Level {
+    pub fn load(&mut self, name: &str, assets: &AssetManager) -> Rc<Level> {
         let i = self
             .level_names
             .iter()
@@ -66,19 +83,35 @@ impl LevelManager {
             .find_map(|(i, level_name)| if level_name == name { Some(i) } else { None })
             .unwrap();
 
-        let level = read_level(&self.levels[i]).unwrap();
+        let level = assets
+            .level(&self.levels[i])
+            .unwrap_or_else(|error| panic!("The level {name} could not be loaded: {error}"));
 
-        self.current = Some(level);
-        self.current.as_ref().unwrap()
+        self.current = Some(level.clone());
+        self.current_name = Some(name.to_owned());
+        level.get()
     }
 
-    fn names(&self) -> &[String] {
-        todo!()
+    /// Returns the names of all the levels in the order they should be played.
+    pub fn names(&self) -> &[String] {
+        &self.level_names
     }
 }
 
 #[derive(Serialize, Deserialize, Debug, Default, Clone)]
-pub struct GameState {}
+pub struct GameState {
+    /// The names of the levels the player has completed.
+    #[serde(default)]
+    pub completed: Vec<String>,
+}
+
+/// The result of updating the current level for a single frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Playing,
+    Completed,
+    GameOver,
+}
 
 #[derive(Debug, Clone)]
 pub struct Game {
@@ -86,14 +119,122 @@ pub struct Game {
     state: GameState,
 
     died: Option<Instant>,
+
+    segment: usize,
+    time_left: Option<Duration>,
+    hurry: bool,
+    /// The music that was last requested from the audio manager.
+    music: Option<PathBuf>,
+    /// Keeps the audio of the current level loaded.
+    audio: Vec<Handle<Audio>>,
+    tiles: TileTable,
+    /// Gameplay is paused until the transition has finished.
+    transition: TransitionState,
 }
 
 impl Game {
-    fn load_level(&mut self, level_name: &str, scene: &mut Scene) {
-        let level = self.level_manager.load(level_name);
+    pub fn load_level(&mut self, level_name: &str, scene: &mut Scene, systems: &GameSystems) {
+        self.died = None;
+        self.hurry = false;
+        self.music = None;
+
+        let level = self.level_manager.load(level_name, &systems.assets);
+        self.time_left = level.time.map(|time| Duration::from_secs(time as u64));
+
+        let music = level.segments.iter().filter_map(|segment| segment.music.as_ref());
+        let tracks = music.flat_map(|music| [Some(&music.track), music.hurry.as_ref()]).flatten();
+        let audio = level.preload.iter().chain(tracks);
+        self.audio = audio
+            .filter(|path| {
+                // A level can refer to audio that doesn't exist, which is skipped
+                // instead of stopping the game.
+                let registered = systems.audio.registered(path);
+                if !registered {
+                    eprintln!("The level {level_name} uses the unknown audio {}", path.display());
+                }
+
+                registered
+            })
+            .map(|path| systems.assets.audio(path))
+            .collect();
+
+        // Nothing of the previous level is left behind, even if this one has no segment to
+        // start in.
+        scene.enemies.clear();
+        scene.entities.clear();
+        scene.layers.clear();
+        scene.backgrounds.clear();
+        scene.camera.bounds = None;
+
         if let Some(start) = level.start {
             self.load_segment(start, scene);
         }
+
+        scene.player_behind = false;
+        self.transition = TransitionState::new([
+            Step::TitleCard(level.name.clone()),
+            Step::CoverIn(TransitionStyle::Fade),
+        ]);
+        self.update_transition(scene, Duration::ZERO);
+    }
+
+    /// Sinks the player into the pipe they are standing on and moves them to
+    /// `segment_id` once they are out of sight.
+    fn enter_pipe(&mut self, segment_id: usize, scene: &mut Scene) {
+        let level = self
+            .level_manager
+            .current
+            .as_ref()
+            .expect("No level is loaded")
+            .get();
+
+        let style = level.segments[segment_id].transition;
+        scene.player_behind = true;
+        self.transition = TransitionState::new([
+            Step::EnterPipe {
+                from: scene.player.position,
+                depth: scene.player.collider().height,
+            },
+            Step::CoverOut(style),
+            Step::LoadSegment(segment_id),
+            Step::CoverIn(style),
+        ]);
+    }
+
+    /// Plays the transition for `delta` and shows where it's at in `scene`.
+    fn update_transition(&mut self, scene: &mut Scene, delta: Duration) {
+        for step in self.transition.advance(delta) {
+            if let Step::LoadSegment(segment_id) = step {
+                self.load_segment(segment_id, scene);
+
+                // Players that are spawned on top of a pipe come up out of it.
+                if standing_on_pipe(scene) {
+                    let to = scene.player.position;
+                    let depth = scene.player.collider().height;
+                    scene.player.position.y += depth;
+                    self.transition.push(Step::ExitPipe { to, depth });
+                }
+            }
+        }
+
+        scene.cover = None;
+        let Some((step, progress)) = self.transition.current() else {
+            scene.player_behind = false;
+            return;
+        };
+
+        match *step {
+            Step::EnterPipe { from, depth } => {
+                scene.player.position = from + vec2(0.0, depth * progress);
+            }
+            Step::ExitPipe { to, depth } => {
+                scene.player.position = to + vec2(0.0, depth * (1.0 - progress));
+            }
+            _ => {}
+        }
+
+        let focus = scene.player.collider().center();
+        scene.cover = step.cover(progress, focus, &scene.camera);
     }
 
     fn load_segment(&mut self, segment_id: usize, scene: &mut Scene) {
@@ -101,235 +242,238 @@ impl Game {
             .level_manager
             .current
             .as_ref()
-            .expect("No level is loaded");
+            .expect("No level is loaded")
+            .get();
 
         let segment = &level.segments[segment_id];
+        self.segment = segment_id;
 
         if let Some(spawn) = segment.spawn {
-            scene.player.position = spawn.as_vec2();
-            scene.camera.position = vec2(0.0, 0.0);
+            scene.player.position = spawn;
+        }
 
-            if spawn.x as f32 > (Renderer::TILES_X * Renderer::TILE_SIZE) as f32 * 0.5 {
-                scene.camera.position.x = spawn.x as f32 - (Renderer::TILES_X * Renderer::TILE_SIZE) as f32 * 0.5;
-            }
-            
+        scene.camera.bounds = Some(segment.bounds());
+        scene.camera.scroll_back = !segment.no_scroll_back;
+        scene.camera.snap_to(scene.player.collider().center());
+
+        scene.enemies = segment.spawn_enemies();
+        scene.entities = segment.entities.clone();
+        scene.layers = segment.layers.clone();
+        scene.background = segment.background;
+        scene.backgrounds = segment.backgrounds.clone();
+    }
+
+    /// Shows the changes to the current level when its file has been reloaded.
+    ///
+    /// The player stays where they are, but the rest of the segment starts over.
+    pub fn reload(&mut self, path: &Path, scene: &mut Scene) {
+        let Some(level) = &self.level_manager.current else { return };
+        if level.path() != path {
+            return;
         }
 
-        scene.enemies = segment.enemies.clone();
+        let level = level.get();
+        let Some(segment) = level.segments.get(self.segment) else { return };
+        scene.enemies = segment.spawn_enemies();
         scene.entities = segment.entities.clone();
-        scene.tiles = segment.tiles.clone();
+        scene.layers = segment.layers.clone();
         scene.background = segment.background;
+        scene.backgrounds = segment.backgrounds.clone();
+        scene.camera.bounds = Some(segment.bounds());
+    }
+
+    /// Makes sure the music of the current segment is playing, switching to the
+    /// hurry up variant when the player is running out of time.
+    fn update_music(&mut self, systems: &GameSystems) {
+        const FADE: Duration = Duration::from_millis(500);
+
+        let Some(level) = self.level_manager.current.as_ref().map(Handle::get) else { return };
+        let music = level.segments.get(self.segment).and_then(|segment| segment.music.as_ref());
+        let track = music.map(|music| match &music.hurry {
+            Some(hurry) if self.hurry => hurry.clone(),
+            _ => music.track.clone(),
+        });
+
+        if track != self.music {
+            match &track {
+                Some(track) => {
+                    systems.audio.play_music(track, FADE);
+                }
+                None => systems.audio.stop_music(FADE),
+            }
+
+            self.music = track;
+        }
     }
 }
 
 impl Game {
     const SAVE_PATH: &str = "./assets/save.json";
-    const LEVEL_PATH: &str = "./assets/levels/";
+    pub const LEVEL_PATH: &str = "./assets/levels/";
 
-    pub fn new(scene: &mut Scene, systems: &GameSystems) -> Self {
+    pub fn new() -> Self {
+        // Levels are only ever read by the game. They are created by hand or with the
+        // editor and are never written back to disk from here.
         let level_manager = LevelManager::new(&Self::LEVEL_PATH);
 
-        let level = Level {
-            name: "test".to_string(),
-            difficulty: Difficulty::Easy,
-            start: Some(0),
-            segments: vec![
-                Segment {
-                    spawn: Some(uvec2(20, 2)),
-                    enemies: vec![
-                        Enemy {
-                            position: vec2(64.0, 210.0),
-                            kind: EnemyKind::Goomba {
-                                from: vec2(20.0, 210.0),
-                                to: vec2(100.0, 210.0),
-                                direction: Direction::Forward,
-                                frame: RefCell::new(0),
-                            },
-                        },
-                        Enemy {
-                            position: vec2(
-                                200.0,
-                                ((Renderer::TILES_Y - 12) * Renderer::TILE_SIZE) as f32 - 8.0,
-                            ),
-                            kind: EnemyKind::Koopa {
-                                direction: Direction::Forward,
-                                shell: None,
-                                frame: RefCell::new(0),
-                            },
-                        },
-                        Enemy {
-                            position: vec2(104.0 + 8.0, 224.0 - 24.0),
-                            kind: EnemyKind::Piranha {
-                                frame: RefCell::new(0),
-                            },
-                        },
-                    ],
-                    entities: vec![Entity {
-                        kind: EntityKind::Pipe { id: 0 },
-                        position: uvec2(104, 224),
-                    },
-                    Entity {
-                        kind: EntityKind::Pipe { id: 1 },
-                        position: uvec2(300, 224),
-                    }],
-                    tiles: {
-                        let mut tiles = Vec::with_capacity((Renderer::TILES_X * 4) as _);
-                        for i in 0..(2 * Renderer::TILES_X) {
-                            for j in 0..2 {
-                                let x = i;
-                                let y = (Renderer::TILES_Y - 2) + j;
-
-                                tiles.push(MapTile {
-                                    block: Block::Ground,
-                                    coordinate: uvec2(x, y),
-                                })
-                            }
-                        }
-                        
-                        // Stair thingy
-                        for i in 0..6 {
-                            for j in i..6 {
-                                tiles.push(MapTile {
-                                    block: Block::Stone,
-                                    coordinate: uvec2(j + 10, Renderer::TILES_Y - i - 3),
-                                })
-                            }
-                        }
+        let file = File::open(Self::SAVE_PATH).ok();
+        let state = file.map(|file| json::from_reader(file).unwrap());
 
-                        for i in 0..1 {
-                            for j in 0..6 {
-                                tiles.push(MapTile {
-                                    block: Block::Stone,
-                                    coordinate: uvec2(i + 16, Renderer::TILES_Y - j - 3),
-                                })
-                            }
-                        }
+        let tiles = TileTable::load(&TileTable::PATH).expect("Could not load the tile table");
 
-                        for i in 0..4 {
-                            tiles.push(MapTile { block: Block::Ground, coordinate: uvec2(i + 2, 13) });
-                        }
+        Self {
+            level_manager,
+            state: state.unwrap_or_default(),
+            died: None,
+            segment: 0,
+            time_left: None,
+            hurry: false,
+            music: None,
+            audio: Vec::new(),
+            tiles,
+            transition: TransitionState::default(),
+        }
+    }
 
-                        tiles
-                    },
-                    background: uvec3(146, 144, 255),
-                },
-                Segment {
-                    spawn: Some(uvec2(30, 20)),
-                    enemies: vec![
-                        Enemy {
-                            kind: EnemyKind::Koopa { direction: Direction::Forward, shell: None, frame: RefCell::new(0) },
-                            position: vec2(16.0 * 10.0, ((Renderer::TILES_Y - 4) * Renderer::TILE_SIZE) as f32)
-                        },
-                        Enemy {
-                            kind: EnemyKind::Koopa { direction: Direction::Forward, shell: None, frame: RefCell::new(0) },
-                            position: vec2(16.0 * 12.0, ((Renderer::TILES_Y - 4) * Renderer::TILE_SIZE) as f32)
-                        },
-                        Enemy {
-                            kind: EnemyKind::Koopa { direction: Direction::Forward, shell: None, frame: RefCell::new(0) },
-                            position: vec2(16.0 * 14.0, ((Renderer::TILES_Y - 4) * Renderer::TILE_SIZE) as f32)
-                        }
-                    ],
-                    tiles: {
-                        let mut tiles = Vec::with_capacity((Renderer::TILES_X * 4) as _);
-                        for i in 0..Renderer::TILES_X {
-                            for j in 0..2 {
-                                let x = i;
-                                let y = (Renderer::TILES_Y - 2) + j;
-
-                                tiles.push(MapTile {
-                                    block: Block::Ground,
-                                    coordinate: uvec2(x, y),
-                                })
-                            }
-                        }
+    /// Returns how much time the player has left to complete the level, if the
+    /// level has a time limit.
+    pub fn time_left(&self) -> Option<Duration> {
+        self.time_left
+    }
 
-                        tiles
-                    },
-                    entities: Vec::default(),
-                    background: uvec3(146, 144, 255),
-                },
-            ],
-        };
+    /// Returns whether the player has died and the level is about to restart.
+    pub fn is_dead(&self) -> bool {
+        self.died.is_some()
+    }
 
-        write_level(&"./assets/levels/Level 2.level", &level).unwrap();
+    /// Returns the names of all the levels in the order they should be played.
+    pub fn level_names(&self) -> &[String] {
+        self.level_manager.names()
+    }
 
-        let file = File::open("./assets/save.json").ok();
-        let state = file.map(|file| json::from_reader(file).unwrap());
+    /// Returns whether there is a level called `name`.
+    pub fn has_level(&self, name: &str) -> bool {
+        self.level_manager.names().iter().any(|level| level == name)
+    }
 
-        let mut game = Self {
-            level_manager,
-            state: state.unwrap_or_default(),
-            died: None,
-        };
+    /// Returns whether the player has completed the level called `name`.
+    pub fn is_completed(&self, name: &str) -> bool {
+        self.state.completed.iter().any(|completed| completed == name)
+    }
 
-        game.load_level("Level 2", scene);
-        game
+    /// Returns the first level the player hasn't completed yet.
+    ///
+    /// If all levels have been completed the first level is returned instead.
+    pub fn next_level(&self) -> Option<&str> {
+        let names = self.level_manager.names();
+        names
+            .iter()
+            .find(|name| !self.is_completed(name))
+            .or(names.first())
+            .map(String::as_str)
     }
 
-    pub fn update(&mut self, scene: &mut Scene, systems: &GameSystems, keyboard: KeyboardState) {
-        if self.died.is_some() {
-            self.handle_died();
+    /// Starts the current level over from the beginning.
+    pub fn restart(&mut self, scene: &mut Scene, systems: &GameSystems) {
+        let name = self.level_manager.current_name.clone().expect("No level is loaded");
+        self.load_level(&name, scene, systems);
+    }
+
+    pub fn update(&mut self, scene: &mut Scene, systems: &GameSystems, keyboard: &KeyboardState, delta: Duration) -> Outcome {
+        /// When there is this little time left the player is warned and the music speeds up.
+        const HURRY_TIME: Duration = Duration::from_secs(100);
+
+        if !self.transition.is_finished() {
+            self.update_transition(scene, delta);
+            return Outcome::Playing;
+        }
+
+        if let Some(died) = self.died {
+            const DURATION: Duration = Duration::from_secs(2);
+            if died.elapsed() >= DURATION {
+                return Outcome::GameOver;
+            }
         } else {
+            if let Some(time_left) = &mut self.time_left {
+                *time_left = time_left.saturating_sub(delta);
+
+                if time_left.is_zero() {
+                    self.die(systems);
+                    return Outcome::Playing;
+                }
+
+                if *time_left <= HURRY_TIME && !self.hurry {
+                    self.hurry = true;
+                    systems.audio.start_with(&Sound::Warning, PlayOptions::with_priority(Priority::High));
+                }
+            }
+
+            self.update_music(systems);
+
+            // Check if the player has reached the end of the level.
+            let reached_flag = scene.entities.iter().any(|entity| {
+                entity.kind == EntityKind::Flag && scene.player.collider().collides_with(&entity.collider()).is_some()
+            });
+
+            if reached_flag {
+                self.complete_level(systems);
+                return Outcome::Completed;
+            }
+
             // Check if the current segment of the level has changed.
             for entity in scene.entities.clone() {
                 if let EntityKind::Pipe { id } = entity.kind {
                     if matches!(scene.player.collider().collides_with(&entity.collider()), Some((Hit::Top, _))) && keyboard.is_scancode_pressed(Scancode::S) {
-                        systems.audio.start(&"./assets/audio/sfx/pipe.wav");
-                        self.load_segment(id, scene);
-                        return;
+                        systems.audio.start_with(&Sound::Pipe, PlayOptions::at(entity.collider().center()));
+                        self.enter_pipe(id, scene);
+                        return Outcome::Playing;
                     }
                 }
             }
 
 
             
-            self.move_player(scene, &keyboard, systems);
+            self.move_player(scene, keyboard, systems);
             
             // Check if the player has fallen to their death
             if scene.player.position.y > (Renderer::TILES_Y * Renderer::TILES_Y) as f32 - 16.0 {
-                self.died = Some(Instant::now());
-                systems.audio.start(&"./assets/audio/clips/mariodie.wav");
-                return;
+                self.die(systems);
+                return Outcome::Playing;
             }
     
             Self::update_enemies(self, scene, systems);
 
-            // Move camera with the player
-            if scene.player.position.x > (Renderer::TILES_X * Renderer::TILE_SIZE) as f32 * 0.5 {
-                scene.camera.position.x = scene.player.position.x - (Renderer::TILES_X * Renderer::TILE_SIZE) as f32 * 0.5;
+            scene.player.animate(delta);
+            for enemy in &mut scene.enemies {
+                enemy.animate(delta);
             }
+
+            scene.camera.follow(scene.player.collider().center(), delta);
+
+            // Sounds are heard from the middle of the screen.
+            systems.audio.set_listener(Listener {
+                position: scene.camera.center(),
+                radius: scene.camera.size.x * 0.5,
+            });
         }
 
+        Outcome::Playing
     }
 
-    fn handle_died(&mut self) {
-        const DURATION: Duration = Duration::from_secs(2);
-        let died = self.died.unwrap();
+    fn die(&mut self, systems: &GameSystems) {
+        self.died = Some(Instant::now());
+        systems.audio.stop_music(Duration::ZERO);
+        systems.audio.start_with(&Sound::MarioDie, PlayOptions::with_priority(Priority::High));
+    }
 
-        if died.elapsed() >= DURATION {
-            let buttons = [
-                ButtonData {
-                    flags: MessageBoxButtonFlag::NOTHING,
-                    button_id: 1,
-                    text: "Ohh noo",
-                }
-            ];
-    
-            let btn = show_message_box(
-                        MessageBoxFlag::empty(),
-                        &buttons,
-                        "Game Over",
-                        "You died, poor loser",
-                        None,
-                        None
-                    )
-                    .unwrap();
-
-            if let ClickedButton::CustomButton(btn) = btn {
-                if btn.button_id == 1 {
-                        panic!("Get good");
-                }
-            }
+    fn complete_level(&mut self, systems: &GameSystems) {
+        systems.audio.stop_music(Duration::ZERO);
+        systems.audio.start_with(&Sound::StageClear, PlayOptions::with_priority(Priority::High));
+
+        let name = self.level_manager.current_name.clone().unwrap();
+        if !self.state.completed.contains(&name) {
+            self.state.completed.push(name);
+            self.save();
         }
     }
 
@@ -345,7 +489,7 @@ impl Game {
                 .collect();
 
             let player = scene.player.clone();
-            let tiles = scene.tiles.clone();
+            let tiles = scene.ground_tiles(&game.tiles);
             async move { Self::update_goombas(goombas, &player, &tiles) }
         };
 
@@ -358,7 +502,7 @@ impl Game {
                 .collect();
 
             let player = scene.player.clone();
-            let tiles = scene.tiles.clone();
+            let tiles = scene.ground_tiles(&game.tiles);
             async move { Self::update_koopas(koopas, &player, &tiles) }
         };
 
@@ -371,7 +515,7 @@ impl Game {
                 .collect();
 
             let player = scene.player.clone();
-            let tiles = scene.tiles.clone();
+            let tiles = scene.ground_tiles(&game.tiles);
             async move { Self::update_piranhas(piranhas, &player, &tiles) }
         };
 
@@ -381,8 +525,14 @@ impl Game {
             thread_pool.spawn_with_handle(updated_piranhas).unwrap()
         ));
 
-        if scene.enemies.len() > goombas.0.len() + koopas.0.len() + piranhas.0.len() {
-            systems.audio.start(&"./assets/audio/sfx/bump.wav");
+        // Stomped goombas are the only enemies that die.
+        let stomped = scene.enemies.iter().filter(|enemy| enemy.is_goomba() && is_stomped(enemy, &scene.player));
+        for enemy in stomped {
+            let options = PlayOptions {
+                priority: Priority::Low,
+                ..PlayOptions::at(enemy.collider().center())
+            };
+            systems.audio.start_with(&Sound::Bump, options);
         }
 
         scene.enemies.clear();
@@ -392,8 +542,7 @@ impl Game {
 
         let hits = goombas.1 + koopas.1 + piranhas.1;
         if hits >= 1 {
-            systems.audio.start(&"./assets/audio/clips/mariodie.wav");
-            game.died = Some(Instant::now());
+            game.die(systems);
         }
     }
 
@@ -407,12 +556,7 @@ impl Game {
         // Handle Dead
         let mut goombas: Vec<_> = goombas
             .into_iter()
-            .filter(|goomba| {
-                 !matches!(
-                    goomba.collider().collides_with(&player.collider()),
-                    Some((Hit::Bottom, _))
-                )
-            })
+            .filter(|goomba| !is_stomped(goomba, player))
             .collect();
 
         let mut hits = 0;
@@ -510,6 +654,10 @@ impl Game {
     }
 
     pub fn on_destroy(&mut self, scene: &mut Scene) {
+        self.save();
+    }
+
+    fn save(&self) {
         let contents = json::to_string_pretty(&self.state).unwrap();
 
         if write(Self::SAVE_PATH, contents).is_err() {
@@ -526,7 +674,7 @@ impl Game {
         let gravity_acceleration = 0.03;
 
         // handle collision
-        let nearby_tiles = &scene.tiles.clone();
+        let nearby_tiles = &scene.solid_tiles(&self.tiles);
 
         // move left and right
         if keyboard.is_scancode_pressed(Scancode::D) {
@@ -584,14 +732,20 @@ impl Game {
         }
 
         if keyboard.is_scancode_pressed(Scancode::Space) && scene.player.can_jump == true {
-            systems.audio.start(&"./assets/audio/sfx/jump_small.wav");
+            systems.audio.start_with(&Sound::JumpSmall, PlayOptions::at(scene.player.position));
             scene.player.jump_velocity = max_jumpspeed;
         }
         if scene.player.jump_velocity >= 0.0 {
             scene.player.position.y -= scene.player.jump_velocity;
         }
 
-        if let Some(collider) = closest_ground(scene, &nearby_tiles.clone()) {
+        if scene.player.jump_velocity > 0.0 {
+            for position in reveal_hidden_tiles(scene, &self.tiles) {
+                systems.audio.start_with(&Sound::Bump, PlayOptions::at(position));
+            }
+        }
+
+        if let Some(collider) = ground_below(scene, &self.tiles) {
             // not falling...
             scene.player.fall_velocity = 0.0;
             scene.player.jump_velocity = 0.0;
@@ -611,6 +765,56 @@ impl Game {
     }
 }
 
+/// Reveals the hidden tiles the player bumps into from below and returns the
+/// centers of the revealed tiles.
+fn reveal_hidden_tiles(scene: &mut Scene, table: &TileTable) -> Vec<Vec2> {
+    let player = scene.player.collider();
+    let layers = scene.layers.iter_mut().filter(|layer| layer.collision);
+    let mut revealed = Vec::new();
+    for tile in layers.flat_map(|layer| &mut layer.tiles) {
+        if tile.revealed || !table.get(&tile.block).hidden {
+            continue;
+        }
+
+        if let Some((Hit::Bottom, _)) = player.collides_with(&tile.collider()) {
+            tile.revealed = true;
+            scene.player.jump_velocity = 0.0;
+            revealed.push(tile.collider().center());
+        }
+    }
+
+    revealed
+}
+
+/// Returns the ground the player is standing on, if any. One-way platforms can
+/// only be landed on while falling, so they're jumped through from below.
+fn ground_below(scene: &mut Scene, table: &TileTable) -> Option<BoundingBox> {
+    let tiles = if scene.player.jump_velocity > 0.0 {
+        scene.solid_tiles(table)
+    } else {
+        scene.ground_tiles(table)
+    };
+
+    closest_ground(scene, &tiles)
+}
+
+/// Returns whether the player is standing on top of a pipe.
+fn standing_on_pipe(scene: &Scene) -> bool {
+    // Players that are spawned slightly above the pipe still count.
+    let mut feet = scene.player.collider();
+    feet.y += Renderer::TILE_SIZE as f32 * 0.5;
+
+    scene.entities.iter().any(|entity| {
+        matches!(entity.kind, EntityKind::Pipe { .. })
+            && matches!(feet.collides_with(&entity.collider()), Some((Hit::Top, _)))
+    })
+}
+
+/// Returns whether the player lands on top of `enemy`.
+fn is_stomped(enemy: &Enemy, player: &Player) -> bool {
+    matches!(enemy.collider().collides_with(&player.collider()), Some((Hit::Bottom, _)))
+}
+
 /// Returns if there is a tile directly below of `position` in `tiles`.
 ///
 /// If `position` is between two tiles one large bounding box is returned. The
@@ -624,12 +828,11 @@ impl Game {
 pub fn below_of(position: Vec2, size: UVec2, tiles: &[MapTile]) -> Option<BoundingBox> {
     let mut left: Option<MapTile> = None;
     let mut right: Option<MapTile> = None;
+    let coordinate = ((position + size.as_vec2()) / Renderer::TILE_SIZE as f32).floor().as_ivec2();
     for tile in tiles {
-        if tile.coordinate == (position + (size.as_vec2())).as_uvec2() / Renderer::TILE_SIZE {
+        if tile.coordinate == coordinate {
             left = Some(*tile)
-        } else if tile.coordinate
-            == (position + (size.as_vec2())).as_uvec2() / Renderer::TILE_SIZE - 1
-        {
+        } else if tile.coordinate == coordinate - 1 {
             right = Some(*tile);
         }
 
@@ -696,12 +899,14 @@ pub fn closest_side(scene: &mut Scene, tiles: &[MapTile]) -> Option<BoundingBox>
 
 pub fn closest_upper() {}
 
-pub fn coordinate_to_position(coordinate: u32) -> f32 {
+pub fn coordinate_to_position(coordinate: i32) -> f32 {
     coordinate as f32 * 16.0
 }
 
-pub fn position_to_coordinate(position: f32) -> u32 {
-    position as u32 / 16
+/// Returns the coordinate of the tile that contains `position`. Positions left
+/// of or above the origin give negative coordinates.
+pub fn position_to_coordinate(position: f32) -> i32 {
+    (position / 16.0).floor() as i32
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -721,6 +926,10 @@ impl BoundingBox {
         }
     }
 
+    pub fn center(&self) -> Vec2 {
+        vec2(self.x + self.width / 2.0, self.y + self.height / 2.0)
+    }
+
     pub fn collides_with(&self, other: &Self) -> Option<(Hit, f32)> {
         let dx = (self.x + self.width / 2.0) - (other.x + other.width / 2.0);
         let dy = (self.y + self.height / 2.0) - (other.y + other.height / 2.0);
@@ -757,24 +966,121 @@ pub enum Hit {
     Left,
 }
 
-// #[cfg(tests)]
+#[cfg(test)]
 mod tests {
-    use glam::uvec2;
+    use super::*;
+
+    fn scene(player: Vec2, tiles: &[(Block, IVec2)]) -> Scene {
+        let tiles = tiles.iter().map(|&(block, coordinate)| MapTile {
+            block,
+            coordinate,
+            revealed: false,
+        });
+
+        Scene {
+            camera: Camera::new(Renderer::SCREEN_SIZE),
+            enemies: Vec::new(),
+            entities: Vec::new(),
+            player: Player::new(player),
+            text: Vec::new(),
+            layers: vec![TileLayer::solid(tiles.collect())],
+            background: uvec3(0, 0, 0),
+            backgrounds: Vec::new(),
+            player_behind: false,
+            cover: None,
+        }
+    }
 
-    use crate::map::*;
+    fn tile_table() -> TileTable {
+        TileTable::load(&TileTable::PATH).unwrap()
+    }
 
     #[test]
     fn test_collision() {
         let a = MapTile {
             block: Block::Ground,
-            coordinate: uvec2(16, 0),
+            coordinate: ivec2(16, 0),
+            revealed: false,
         };
 
         let b = MapTile {
             block: Block::Ground,
-            coordinate: uvec2(16, 0),
+            coordinate: ivec2(16, 0),
+            revealed: false,
         };
 
         println!("{:?}", a.collider().collides_with(&b.collider()));
     }
+
+    #[test]
+    fn test_platforms_are_landed_on_while_falling() {
+        let table = tile_table();
+        let mut scene = scene(vec2(0.0, 0.0), &[(Block::Platform, ivec2(0, 1))]);
+
+        assert!(ground_below(&mut scene, &table).is_some());
+    }
+
+    #[test]
+    fn test_platforms_are_jumped_through_from_below() {
+        let table = tile_table();
+        let mut scene = scene(vec2(0.0, 0.0), &[(Block::Platform, ivec2(0, 1))]);
+        scene.player.jump_velocity = 1.0;
+
+        assert!(ground_below(&mut scene, &table).is_none());
+        assert!(scene.solid_tiles(&table).is_empty());
+    }
+
+    #[test]
+    fn test_solid_tiles_are_ground_while_jumping() {
+        let table = tile_table();
+        let mut scene = scene(vec2(0.0, 0.0), &[(Block::Ground, ivec2(0, 1))]);
+        scene.player.jump_velocity = 1.0;
+
+        assert!(ground_below(&mut scene, &table).is_some());
+    }
+
+    #[test]
+    fn test_hidden_tiles_are_not_ground_until_revealed() {
+        let table = tile_table();
+        let mut scene = scene(vec2(0.0, 0.0), &[(Block::Hidden, ivec2(0, 1))]);
+        assert!(ground_below(&mut scene, &table).is_none());
+
+        scene.layers[0].tiles[0].revealed = true;
+        assert!(ground_below(&mut scene, &table).is_some());
+    }
+
+    #[test]
+    fn test_hidden_tiles_are_revealed_from_below() {
+        let table = tile_table();
+        let mut scene = scene(vec2(0.0, 10.0), &[(Block::Hidden, ivec2(0, 0))]);
+        scene.player.jump_velocity = 3.0;
+
+        let revealed = reveal_hidden_tiles(&mut scene, &table);
+        assert_eq!(revealed, [vec2(8.0, 8.0)]);
+        assert!(scene.layers[0].tiles[0].revealed);
+        assert_eq!(scene.player.jump_velocity, 0.0);
+
+        // Tiles are only revealed once.
+        assert!(reveal_hidden_tiles(&mut scene, &table).is_empty());
+    }
+
+    #[test]
+    fn test_hidden_tiles_are_not_revealed_from_above() {
+        let table = tile_table();
+        let mut scene = scene(vec2(0.0, -10.0), &[(Block::Hidden, ivec2(0, 0))]);
+
+        assert!(reveal_hidden_tiles(&mut scene, &table).is_empty());
+        assert!(!scene.layers[0].tiles[0].revealed);
+    }
+
+    #[test]
+    fn test_levels_without_a_start_are_skipped() {
+        let levels = LevelManager::new(&Game::LEVEL_PATH);
+        assert!(!levels.names().is_empty());
+
+        for path in &levels.levels {
+            let level = read_level(path).unwrap();
+            assert!(level.start.is_some_and(|start| start < level.segments.len()));
+        }
+    }
 }
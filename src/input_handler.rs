@@ -1,24 +1,129 @@
-use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
+use std::collections::HashSet;
 
+use ::sdl2::controller::{Button, GameController};
+use ::sdl2::keyboard::{KeyboardState, Scancode};
+use ::sdl2::GameControllerSubsystem;
+
+/// An abstract input that can be triggered by either the keyboard or a
+/// controller.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    Up,
+    Down,
+    Left,
+    Right,
+    Confirm,
+    Back,
+    Pause,
+}
+
+impl Action {
+    const ALL: [Action; 7] = [
+        Action::Up,
+        Action::Down,
+        Action::Left,
+        Action::Right,
+        Action::Confirm,
+        Action::Back,
+        Action::Pause,
+    ];
+
+    fn scancodes(&self) -> &'static [Scancode] {
+        match self {
+            Action::Up => &[Scancode::W, Scancode::Up],
+            Action::Down => &[Scancode::S, Scancode::Down],
+            Action::Left => &[Scancode::A, Scancode::Left],
+            Action::Right => &[Scancode::D, Scancode::Right],
+            Action::Confirm => &[Scancode::Return, Scancode::KpEnter],
+            Action::Back => &[Scancode::Backspace],
+            Action::Pause => &[Scancode::Escape, Scancode::P],
+        }
+    }
+
+    fn buttons(&self) -> &'static [Button] {
+        match self {
+            Action::Up => &[Button::DPadUp],
+            Action::Down => &[Button::DPadDown],
+            Action::Left => &[Button::DPadLeft],
+            Action::Right => &[Button::DPadRight],
+            Action::Confirm => &[Button::A],
+            Action::Back => &[Button::B],
+            Action::Pause => &[Button::Start],
+        }
+    }
+}
+
+/// Keeps track of which actions are held down and which were pressed this
+/// frame.
 pub struct InputHandler {
-    mouse_state: sdl2::mouse::MouseState,
+    subsystem: GameControllerSubsystem,
+    controllers: Vec<GameController>,
+    joysticks: u32,
+
+    held: HashSet<Action>,
+    pressed: HashSet<Action>,
 }
 
 impl InputHandler {
-    pub fn new(&mut self, mouse_state: sdl2::mouse::MouseState) -> InputHandler {
-        InputHandler { mouse_state }
+    pub fn new(subsystem: GameControllerSubsystem) -> InputHandler {
+        let mut handler = InputHandler {
+            subsystem,
+            controllers: Vec::new(),
+            joysticks: 0,
+            held: HashSet::new(),
+            pressed: HashSet::new(),
+        };
+
+        handler.open_controllers();
+        handler
     }
 
-    pub fn is_mouse_clicked(&self) -> bool {
-        todo!()
+    /// Opens all the connected controllers, replacing the ones that were
+    /// opened before.
+    fn open_controllers(&mut self) {
+        let count = self.subsystem.num_joysticks().unwrap_or(0);
+
+        self.joysticks = count;
+        self.controllers = (0..count)
+            .filter(|i| self.subsystem.is_game_controller(*i))
+            .filter_map(|i| self.subsystem.open(i).ok())
+            .collect();
     }
 
-    pub fn is_mouse_held(&self) -> bool {
-        if self.is_mouse_clicked() {
-            return false;
+    /// Updates the state of all actions.
+    /// This function should be called once pr. frame before the state is read.
+    pub fn update(&mut self, keyboard: &KeyboardState) {
+        // Controllers can be plugged in and out while the game is running.
+        let count = self.subsystem.num_joysticks().unwrap_or(0);
+        if count != self.joysticks || self.controllers.iter().any(|c| !c.attached()) {
+            self.open_controllers();
         }
 
-        true
+        let held: HashSet<_> = Action::ALL
+            .into_iter()
+            .filter(|action| {
+                let keyboard = action
+                    .scancodes()
+                    .iter()
+                    .any(|scancode| keyboard.is_scancode_pressed(*scancode));
+
+                let controller = self.controllers.iter().any(|controller| {
+                    action
+                        .buttons()
+                        .iter()
+                        .any(|button| controller.button(*button))
+                });
+
+                keyboard || controller
+            })
+            .collect();
+
+        self.pressed = held.difference(&self.held).copied().collect();
+        self.held = held;
+    }
+
+    /// Returns true if `action` went from released to held this frame.
+    pub fn is_pressed(&self, action: Action) -> bool {
+        self.pressed.contains(&action)
     }
 }
@@ -1,12 +1,15 @@
 use std::fs;
 use std::path::*;
+use std::time::Duration;
 
 use ::glam::*;
 use ::serde::{Deserialize, Serialize};
 use ::serde_json as json;
 
 use crate::map::*;
+use crate::renderer::Renderer;
 use crate::scene::*;
+use crate::transition::*;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
 pub enum Difficulty {
@@ -20,24 +23,329 @@ pub struct Level {
     pub name: String,
     pub difficulty: Difficulty,
     pub start: Option<usize>,
+    /// The number of seconds the player has to complete the level.
+    #[serde(default)]
+    pub time: Option<u32>,
+    /// Audio that is loaded in the background as soon as the level is loaded.
+    ///
+    /// The music of the segments doesn't need to be listed here.
+    #[serde(default)]
+    pub preload: Vec<PathBuf>,
     pub segments: Vec<Segment>,
 }
 
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct Segment {
-    pub spawn: Option<UVec2>,
+    pub spawn: Option<Vec2>,
     pub enemies: Vec<Enemy>,
     pub entities: Vec<Entity>,
-    pub tiles: Vec<MapTile>,
+    /// The tiles of the segment from the back to the front.
+    #[serde(default)]
+    pub layers: Vec<TileLayer>,
+    /// The tiles of levels that were saved before segments had layers. They
+    /// are moved into a solid layer when the level is read.
+    #[serde(default, rename = "tiles", skip_serializing)]
+    pub legacy_tiles: Vec<MapTile>,
     pub background: UVec3,
+    /// The layers that are drawn on top of the background color, from the back
+    /// to the front.
+    #[serde(default)]
+    pub backgrounds: Vec<BackgroundLayer>,
+    #[serde(default)]
+    pub music: Option<Music>,
+    /// Keeps the camera from scrolling back to the left, like in the original
+    /// game.
+    #[serde(default)]
+    pub no_scroll_back: bool,
+    /// How the screen changes when the player enters the segment through a
+    /// pipe.
+    #[serde(default)]
+    pub transition: TransitionStyle,
 }
 
+impl Level {
+    /// Creates a level with a single, empty segment to start from.
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            difficulty: Difficulty::Easy,
+            start: Some(0),
+            time: None,
+            preload: Vec::new(),
+            segments: vec![Segment::new()],
+        }
+    }
+}
+
+impl Segment {
+    /// Creates a segment without any tiles, enemies or entities, which has a
+    /// single solid layer to place tiles on.
+    pub fn new() -> Self {
+        Self {
+            spawn: None,
+            enemies: Vec::new(),
+            entities: Vec::new(),
+            layers: vec![TileLayer::solid(Vec::new())],
+            legacy_tiles: Vec::new(),
+            background: uvec3(146, 144, 255),
+            backgrounds: Vec::new(),
+            music: None,
+            no_scroll_back: false,
+            transition: TransitionStyle::default(),
+        }
+    }
+
+    /// Returns the top-left and bottom-right corners of the part of the world
+    /// the segment covers.
+    ///
+    /// The segment is always at least a screen tall, and reaches up from the
+    /// bottom of the world so that the ground is shown at the bottom of the
+    /// screen.
+    pub fn bounds(&self) -> (Vec2, Vec2) {
+        let tiles = self.layers.iter().flat_map(|layer| &layer.tiles);
+        let coordinates = tiles.map(|tile| tile.coordinate);
+        let mut min = coordinates
+            .clone()
+            .reduce(IVec2::min)
+            .unwrap_or(IVec2::ZERO);
+        let mut max = coordinates
+            .map(|coordinate| coordinate + 1)
+            .reduce(IVec2::max)
+            .unwrap_or(IVec2::ZERO);
+
+        let tiles_y = Renderer::TILES_Y as i32;
+        max.y = max.y.max(tiles_y);
+        min.y = min.y.min(max.y - tiles_y);
+
+        let tile_size = Renderer::TILE_SIZE as f32;
+        (min.as_vec2() * tile_size, max.as_vec2() * tile_size)
+    }
+
+    /// Returns copies of the enemies of the segment that can be drawn right
+    /// away.
+    ///
+    /// Animations aren't saved with the level, so every enemy starts out with
+    /// the clip that fits its kind.
+    pub fn spawn_enemies(&self) -> Vec<Enemy> {
+        let mut enemies = self.enemies.clone();
+        for enemy in &mut enemies {
+            enemy.animate(Duration::ZERO);
+        }
+
+        enemies
+    }
+}
+
+/// The background music of a segment.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Music {
+    /// The track that is looped while the segment is being played.
+    pub track: PathBuf,
+    /// The track that replaces `track` when the player is running out of time.
+    #[serde(default)]
+    pub hurry: Option<PathBuf>,
+}
+
+/// Reads the level in `path`.
+///
+/// Levels that were saved before positions could be negative store them as
+/// whole, unsigned numbers. These are read as they are, and the level is
+/// saved in the new format the next time it's written.
 pub fn read_level(path: &impl AsRef<Path>) -> Result<Level, json::Error> {
-    let file = fs::File::open(path).unwrap();
-    json::from_reader(file)
+    let file = fs::File::open(path).map_err(json::Error::io)?;
+    let mut level = json::from_reader(file)?;
+    migrate(&mut level);
+    Ok(level)
+}
+
+/// Brings a level that was saved in an older format up to date.
+fn migrate(level: &mut Level) {
+    for segment in &mut level.segments {
+        let tiles = std::mem::take(&mut segment.legacy_tiles);
+        if !tiles.is_empty() {
+            segment.layers.insert(0, TileLayer::solid(tiles));
+        }
+    }
 }
 
 pub fn write_level(path: &impl AsRef<Path>, level: &Level) -> Result<(), json::Error> {
-    let file = fs::File::create(path).unwrap();
+    let file = fs::File::create(path).map_err(json::Error::io)?;
     json::to_writer_pretty(file, level)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::animation::Animator;
+
+    /// The level the game used to write out every time it started, kept to
+    /// make sure every part of a level can be written and read back.
+    fn test_level() -> Level {
+        let tiles_y = Renderer::TILES_Y as i32;
+        let ground = |width: i32| {
+            let coordinates =
+                (0..width).flat_map(|x| [ivec2(x, tiles_y - 2), ivec2(x, tiles_y - 1)]);
+            coordinates.map(|coordinate| MapTile {
+                block: Block::Ground,
+                coordinate,
+                revealed: false,
+            })
+        };
+
+        let stone = |coordinate| MapTile {
+            block: Block::Stone,
+            coordinate,
+            revealed: false,
+        };
+
+        let mut tiles: Vec<_> = ground(2 * Renderer::TILES_X as i32).collect();
+
+        // Stair thingy
+        for i in 0..6 {
+            for j in i..6 {
+                tiles.push(stone(ivec2(j + 10, tiles_y - i - 3)));
+            }
+        }
+
+        for j in 0..6 {
+            tiles.push(stone(ivec2(16, tiles_y - j - 3)));
+        }
+
+        for i in 0..4 {
+            tiles.push(MapTile {
+                block: Block::Ground,
+                coordinate: ivec2(i + 2, 13),
+                revealed: false,
+            });
+        }
+
+        let enemy = |position, kind| Enemy {
+            position,
+            kind,
+            animator: Animator::default(),
+        };
+
+        let koopa = |x: f32| {
+            let y = ((Renderer::TILES_Y - 4) * Renderer::TILE_SIZE) as f32;
+            let kind = EnemyKind::Koopa {
+                direction: Direction::Forward,
+                shell: None,
+            };
+
+            enemy(vec2(x, y), kind)
+        };
+
+        let flag_y = ((Renderer::TILES_Y - 2) * Renderer::TILE_SIZE) as f32 - 168.0;
+
+        Level {
+            segments: vec![
+                Segment {
+                    spawn: Some(vec2(20.0, 2.0)),
+                    enemies: vec![
+                        enemy(
+                            vec2(64.0, 210.0),
+                            EnemyKind::Goomba {
+                                from: vec2(20.0, 210.0),
+                                to: vec2(100.0, 210.0),
+                                direction: Direction::Forward,
+                            },
+                        ),
+                        koopa(200.0),
+                        enemy(vec2(104.0 + 8.0, 224.0 - 24.0), EnemyKind::Piranha {}),
+                    ],
+                    entities: vec![
+                        Entity {
+                            kind: EntityKind::Pipe { id: 0 },
+                            position: vec2(104.0, 224.0),
+                        },
+                        Entity {
+                            kind: EntityKind::Pipe { id: 1 },
+                            position: vec2(300.0, 224.0),
+                        },
+                        Entity {
+                            kind: EntityKind::Flag,
+                            position: vec2(46.0 * Renderer::TILE_SIZE as f32, flag_y),
+                        },
+                    ],
+                    layers: vec![TileLayer::solid(tiles)],
+                    ..Segment::new()
+                },
+                Segment {
+                    spawn: Some(vec2(30.0, 20.0)),
+                    enemies: vec![koopa(16.0 * 10.0), koopa(16.0 * 12.0), koopa(16.0 * 14.0)],
+                    layers: vec![TileLayer::solid(ground(Renderer::TILES_X as i32).collect())],
+                    ..Segment::new()
+                },
+            ],
+            ..Level::new("test")
+        }
+    }
+
+    #[test]
+    fn test_levels_can_be_written_and_read() {
+        let level = test_level();
+        let path = std::env::temp_dir().join("mario_test_level.level");
+
+        write_level(&path, &level).unwrap();
+        let read = read_level(&path).unwrap();
+        let _ = fs::remove_file(&path);
+
+        assert_eq!(read, level);
+    }
+
+    #[test]
+    fn test_camera_shows_the_ground_of_short_segments() {
+        let level = read_level(&"./assets/levels/Level 2.level").unwrap();
+
+        for segment in &level.segments {
+            let spawn = segment.spawn.unwrap();
+            let mut camera = Camera::new(Renderer::SCREEN_SIZE);
+            camera.bounds = Some(segment.bounds());
+            camera.snap_to(spawn);
+
+            // The segments are shorter than the screen, so the bottom of the
+            // screen lines up with the bottom of the world.
+            let bottom = segment.bounds().1.y;
+            assert_eq!(bottom, Renderer::SCREEN_SIZE.y);
+            assert_eq!(camera.position.y, bottom - camera.size.y);
+            assert!(spawn.y >= camera.position.y && spawn.y < bottom);
+        }
+    }
+
+    #[test]
+    fn test_spawned_enemies_have_an_animation() {
+        let level = test_level();
+        let segment = &level.segments[0];
+        let enemies = segment.spawn_enemies();
+        assert!(!enemies.is_empty());
+        assert_eq!(enemies.len(), segment.enemies.len());
+        assert!(enemies.iter().all(|enemy| enemy.animator.is_playing()));
+    }
+
+    #[test]
+    fn test_old_levels_can_be_read() {
+        let level = r#"{
+            "name": "Old",
+            "difficulty": "Easy",
+            "start": 0,
+            "segments": [{
+                "spawn": [32, 64],
+                "enemies": [],
+                "entities": [{ "position": [48, 16], "kind": "Flag" }],
+                "tiles": [{ "block": "Ground", "coordinate": [3, 17] }],
+                "background": [146, 144, 255]
+            }]
+        }"#;
+
+        let mut level: Level = json::from_str(level).unwrap();
+        migrate(&mut level);
+
+        // Positions used to be unsigned and all tiles used to be solid.
+        let segment = &level.segments[0];
+        assert_eq!(segment.spawn, Some(vec2(32.0, 64.0)));
+        assert_eq!(segment.entities[0].position, vec2(48.0, 16.0));
+        assert!(segment.legacy_tiles.is_empty());
+        assert!(segment.layers[0].collision);
+        assert_eq!(segment.layers[0].tiles[0].coordinate, ivec2(3, 17));
+    }
+}
@@ -8,18 +8,29 @@ use ::sdl2::mouse::*;
 use ::sdl2::video::*;
 
 use self::editor::*;
+use self::game::Game;
 use self::runtime::*;
 
-//mod animation;
+mod animation;
+mod assets;
+mod atlas;
 mod audio;
+mod audio_backend;
+mod decoder;
 mod editor;
+mod font;
 mod game;
 mod input_handler;
 mod level;
 mod map;
+mod menu;
 mod renderer;
 mod runtime;
 mod scene;
+mod screen;
+mod settings;
+mod sound;
+mod transition;
 
 mod os;
 
@@ -47,10 +58,44 @@ pub trait Layer {
 fn main() {
     let sdl = sdl2::init().unwrap();
     let video = sdl.video().unwrap();
-    let audio = sdl.audio().unwrap();
+    // The game is still playable without sound when audio can't be initialized.
+    let audio = sdl
+        .audio()
+        .map_err(|error| eprintln!("SDL audio could not be initialized: {error}"))
+        .ok();
+    let controller = sdl.game_controller().unwrap();
     let mut event_pump = sdl.event_pump().unwrap();
 
-    let runtime = Runtime::new(video.clone(), audio.clone());
+    // A level can be started directly with `--level <name>`, where the name is
+    // either the name of a level or the path to its file in the levels folder.
+    // Levels are only ever read from that folder, so paths anywhere else are
+    // rejected.
+    let args: Vec<_> = std::env::args().collect();
+    let level = args
+        .iter()
+        .position(|arg| arg == "--level")
+        .and_then(|i| args.get(i + 1))
+        .and_then(|level| {
+            let path = std::path::Path::new(level);
+            let directory = path
+                .parent()
+                .filter(|parent| !parent.as_os_str().is_empty());
+            if let Some(directory) = directory {
+                let levels = std::path::Path::new(Game::LEVEL_PATH).canonicalize().ok();
+                if directory.canonicalize().ok() != levels {
+                    eprintln!(
+                        "Only levels in {} can be played, not {level}",
+                        Game::LEVEL_PATH
+                    );
+                    return None;
+                }
+            }
+
+            let name = path.file_stem().and_then(|name| name.to_str());
+            Some(name.unwrap_or(level).to_owned())
+        });
+
+    let runtime = Runtime::new(video.clone(), audio.clone(), controller, level);
     let mut layers: Vec<Option<Box<dyn Layer>>> = vec![Some(Box::new(runtime))];
 
     if std::env::args().any(|arg| arg.contains("editor")) {
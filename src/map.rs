@@ -1,5 +1,10 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::Path;
+
 use ::glam::*;
 use ::serde::{Deserialize, Serialize};
+use ::serde_json as json;
 
 use crate::game::BoundingBox;
 use crate::renderer::Renderer;
@@ -12,12 +17,47 @@ pub enum Block {
     WallInside,
     Stone,
     Question(Option<Item>),
+    Platform,
+    Hidden,
+    Bush,
+}
+
+impl Block {
+    /// Every block that doesn't carry any data.
+    pub const PLAIN: [Block; 7] = [
+        Block::Ground,
+        Block::Wall,
+        Block::WallInside,
+        Block::Stone,
+        Block::Platform,
+        Block::Hidden,
+        Block::Bush,
+    ];
+
+    /// The name the block is listed under in the tile table.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Block::Ground => "Ground",
+            Block::Wall => "Wall",
+            Block::WallInside => "WallInside",
+            Block::Stone => "Stone",
+            Block::Question(_) => "Question",
+            Block::Platform => "Platform",
+            Block::Hidden => "Hidden",
+            Block::Bush => "Bush",
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
 pub struct MapTile {
     pub block: Block,
-    pub coordinate: UVec2,
+    /// The position of the tile in tiles. Tiles can be placed left of and
+    /// above the origin.
+    pub coordinate: IVec2,
+    /// Whether a hidden tile has been bumped into view.
+    #[serde(skip)]
+    pub revealed: bool,
 }
 
 impl MapTile {
@@ -33,30 +73,117 @@ impl MapTile {
     }
 }
 
-impl ToSprite for Block {
-    fn to_sprite(&self) -> Sprite {
-        match self {
-            Block::Ground => Sprite::new(
-                (uvec2(0, 16), uvec2(16, 16)),
-                "./assets/sprites/tilesheet.png",
-                false,
-            ),
-            Block::Wall => Sprite::new(
-                (uvec2(17, 16), uvec2(16, 16)),
-                "./assets/sprites/tilesheet.png",
-                false,
-            ),
-            Block::WallInside => Sprite::new(
-                (uvec2(34, 16), uvec2(16, 16)),
-                "./assets/sprites/tilesheet.png",
-                false,
-            ),
-            Block::Stone => Sprite::new(
-                (uvec2(0, 33), uvec2(16, 16)),
-                "./assets/sprites/tilesheet.png",
-                false,
-            ),
-            Block::Question { .. } => todo!(),
+/// How the player and enemies collide with a tile.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+pub enum Collision {
+    #[default]
+    Solid,
+    /// Can be jumped through from below and stood on from above.
+    OneWay,
+    /// Decoration that is never collided with.
+    None,
+}
+
+/// What a kind of block looks like and how it behaves.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct TileProperties {
+    /// The frame of the block in the atlas of the tile table.
+    pub frame: String,
+    #[serde(default)]
+    pub collision: Collision,
+    /// Hidden tiles can't be seen or collided with until they are bumped from
+    /// below.
+    #[serde(default)]
+    pub hidden: bool,
+}
+
+/// The properties of every kind of block by the name of the block.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct TileTable {
+    pub atlas: String,
+    pub blocks: HashMap<String, TileProperties>,
+}
+
+impl TileTable {
+    pub const PATH: &str = "./assets/tiles.json";
+
+    pub fn load(path: &impl AsRef<Path>) -> Result<Self, json::Error> {
+        let file = File::open(path).map_err(json::Error::io)?;
+        json::from_reader(file)
+    }
+
+    /// # Panics
+    /// Panics if the table doesn't list `block`.
+    pub fn get(&self, block: &Block) -> &TileProperties {
+        let Some(properties) = self.blocks.get(block.name()) else {
+            panic!("There are no tile properties for {}", block.name());
+        };
+
+        properties
+    }
+
+    pub fn sprite(&self, block: &Block) -> Sprite {
+        Sprite::new(&self.atlas, &self.get(block).frame, false)
+    }
+
+    /// Returns the sprites of every block in the table.
+    pub fn sprites(&self) -> impl Iterator<Item = Sprite> + '_ {
+        let frames = self.blocks.values().map(|properties| &properties.frame);
+        frames.map(|frame| Sprite::new(&self.atlas, frame, false))
+    }
+
+    /// Returns a block of every kind that is listed in the table.
+    ///
+    /// Question blocks hold a mushroom, since the item isn't part of the table.
+    pub fn blocks(&self) -> Vec<Block> {
+        let question = Block::Question(Some(Item::Mushroom));
+        let blocks = Block::PLAIN.into_iter().chain([question]);
+
+        let listed = |block: &Block| self.blocks.contains_key(block.name());
+        blocks.filter(listed).collect()
+    }
+
+    /// Returns whether `tile` can be seen. Hidden tiles can only be seen once
+    /// they have been revealed.
+    pub fn is_visible(&self, tile: &MapTile) -> bool {
+        !self.get(&tile.block).hidden || tile.revealed
+    }
+}
+
+/// A set of tiles that are drawn together.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct TileLayer {
+    pub tiles: Vec<MapTile>,
+    /// Whether the player and enemies collide with the tiles.
+    pub collision: bool,
+    /// Whether the tiles are drawn in front of the player and enemies.
+    #[serde(default)]
+    pub foreground: bool,
+}
+
+impl TileLayer {
+    /// Creates a layer the player and enemies collide with.
+    pub fn solid(tiles: Vec<MapTile>) -> Self {
+        Self {
+            tiles,
+            collision: true,
+            foreground: false,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_every_block_is_in_the_table() {
+        let table = TileTable::load(&TileTable::PATH).unwrap();
+        let blocks = table.blocks();
+        assert_eq!(blocks[..Block::PLAIN.len()], Block::PLAIN);
+        assert_eq!(
+            blocks[Block::PLAIN.len()..],
+            [Block::Question(Some(Item::Mushroom))]
+        );
+    }
+}
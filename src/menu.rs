@@ -0,0 +1,43 @@
+use crate::input_handler::*;
+
+/// A vertical list of items that can be navigated with the keyboard or a
+/// controller.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Menu {
+    pub title: String,
+    pub items: Vec<String>,
+    pub selected: usize,
+}
+
+impl Menu {
+    pub fn new(title: impl Into<String>, items: Vec<String>) -> Self {
+        Self {
+            title: title.into(),
+            items,
+            selected: 0,
+        }
+    }
+
+    /// Moves the selection according to the actions pressed this frame.
+    ///
+    /// Returns the index of the item that was confirmed, if any.
+    pub fn handle_input(&mut self, input: &InputHandler) -> Option<usize> {
+        if self.items.is_empty() {
+            return None;
+        }
+
+        if input.is_pressed(Action::Up) {
+            self.selected = self.selected.checked_sub(1).unwrap_or(self.items.len() - 1);
+        }
+
+        if input.is_pressed(Action::Down) {
+            self.selected = (self.selected + 1) % self.items.len();
+        }
+
+        if input.is_pressed(Action::Confirm) {
+            Some(self.selected)
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,935 @@
+//! The platform used everywhere but Windows. The menu bar and the file dialogs
+//! are drawn inside the window with SDL and the built-in font.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use ::glam::*;
+use ::sdl2::event::{Event, WindowEvent};
+use ::sdl2::keyboard::Keycode;
+use ::sdl2::mouse::MouseButton;
+use ::sdl2::pixels::Color;
+use ::sdl2::rect::{Point, Rect};
+use ::sdl2::render::{BlendMode, WindowCanvas};
+use ::sdl2::video::Window;
+use ::sdl2::VideoSubsystem;
+
+use crate::font;
+use crate::os::*;
+
+const SCALE: u32 = 2;
+const PADDING: u32 = 8;
+/// The height of the menu bar and of every item in the menus and the file
+/// browser.
+const ROW_HEIGHT: u32 = font::GLYPH_HEIGHT * SCALE + PADDING;
+
+const BACKGROUND: Color = Color::RGB(243, 244, 246);
+const HIGHLIGHT: Color = Color::RGB(209, 213, 219);
+const BORDER: Color = Color::RGB(156, 163, 175);
+const TEXT: Color = Color::RGB(17, 24, 39);
+const DIMMED_TEXT: Color = Color::RGB(75, 85, 99);
+
+pub fn new_window(
+    video: &VideoSubsystem,
+    title: &str,
+    size: UVec2,
+    menu: Vec<MenuItem>,
+) -> (LinuxPlatform, Window) {
+    let window = video
+        .window(title, size.x, size.y)
+        .position_centered()
+        .resizable()
+        .build()
+        .unwrap();
+
+    let mut actions = Vec::new();
+    let menu = Entry::from_items(menu, &mut actions);
+
+    let platform = LinuxPlatform {
+        menu,
+        actions,
+        open: Vec::new(),
+        clicked: Vec::new(),
+        mouse: IVec2::ZERO,
+        window_size: size,
+        browser: None,
+        picked: None,
+    };
+
+    (platform, window)
+}
+
+fn text_width(text: &str) -> u32 {
+    font::text_width(text) * SCALE
+}
+
+/// Draws `text` inside the row that starts at `position`.
+fn draw_text(canvas: &mut WindowCanvas, text: &str, position: IVec2, color: Color) {
+    let offset = ivec2(PADDING as i32, PADDING as i32 / 2);
+    font::draw_text(canvas, text, position + offset, SCALE, color);
+}
+
+pub struct LinuxPlatform {
+    menu: Vec<Entry>,
+    actions: Vec<Box<dyn Fn()>>,
+    /// The index of every menu that is open, starting from the menu bar.
+    open: Vec<usize>,
+    /// The actions that have been picked since the last update.
+    clicked: Vec<usize>,
+    mouse: IVec2,
+    window_size: UVec2,
+
+    browser: Option<FileBrowser>,
+    picked: Option<(FileDialog, PathBuf)>,
+}
+
+/// A menu item that owns its title. Actions are stored by their index into the
+/// actions of the platform.
+enum Entry {
+    Divider,
+    Action { title: String, action: usize },
+    SubMenu { title: String, entries: Vec<Entry> },
+}
+
+impl Entry {
+    fn from_items(items: Vec<MenuItem>, actions: &mut Vec<Box<dyn Fn()>>) -> Vec<Entry> {
+        let entry = |item| match item {
+            MenuItem::Divider => Entry::Divider,
+            MenuItem::Action { title, action } => {
+                actions.push(action);
+                Entry::Action {
+                    title: title.to_owned(),
+                    action: actions.len() - 1,
+                }
+            }
+            MenuItem::SubMenu { title, items } => Entry::SubMenu {
+                title: title.to_owned(),
+                entries: Self::from_items(items, actions),
+            },
+        };
+
+        items.into_iter().map(entry).collect()
+    }
+
+    fn title(&self) -> &str {
+        match self {
+            Entry::Divider => "",
+            Entry::Action { title, .. } | Entry::SubMenu { title, .. } => title,
+        }
+    }
+
+    fn height(&self) -> u32 {
+        match self {
+            Entry::Divider => PADDING + 1,
+            _ => ROW_HEIGHT,
+        }
+    }
+}
+
+impl LinuxPlatform {
+    /// Returns where every item of the menu bar and of the open menus is, with
+    /// the menu bar first.
+    fn panels(&self) -> Vec<Vec<(Rect, &Entry)>> {
+        let mut x = 0;
+        let bar = self.menu.iter().map(|entry| {
+            let rect = Rect::new(x, 0, text_width(entry.title()) + PADDING * 2, ROW_HEIGHT);
+            x += rect.width() as i32;
+            (rect, entry)
+        });
+
+        let mut panels = vec![bar.collect::<Vec<_>>()];
+        for &i in &self.open {
+            let Some(&(parent, Entry::SubMenu { entries, .. })) = panels.last().unwrap().get(i)
+            else {
+                break;
+            };
+
+            // Menus in the menu bar open below it, and the rest to the right of
+            // their parent.
+            let origin = if panels.len() == 1 {
+                ivec2(parent.x(), parent.bottom())
+            } else {
+                ivec2(parent.right(), parent.y())
+            };
+
+            let titles = entries.iter().map(|entry| text_width(entry.title()));
+            let width = titles.max().unwrap_or(0) + PADDING * 4;
+
+            let mut y = origin.y;
+            let panel = entries.iter().map(|entry| {
+                let rect = Rect::new(origin.x, y, width, entry.height());
+                y += rect.height() as i32;
+                (rect, entry)
+            });
+
+            panels.push(panel.collect());
+        }
+
+        panels
+    }
+
+    /// Returns the depth and index of the item at `point`.
+    fn item_at(&self, point: IVec2) -> Option<(usize, usize)> {
+        let point = Point::new(point.x, point.y);
+        let panels = self.panels();
+
+        // Menus that are opened later are drawn on top.
+        panels.iter().enumerate().rev().find_map(|(depth, panel)| {
+            let i = panel
+                .iter()
+                .position(|(rect, _)| rect.contains_point(point))?;
+            Some((depth, i))
+        })
+    }
+
+    /// Returns whether the click was on the menu bar or an open menu.
+    fn click(&mut self, point: IVec2) -> bool {
+        let Some((depth, i)) = self.item_at(point) else {
+            // Clicking anywhere else closes the menus.
+            let was_open = !self.open.is_empty();
+            self.open.clear();
+            return was_open || point.y < ROW_HEIGHT as i32;
+        };
+
+        let action = match self.panels()[depth][i].1 {
+            Entry::Divider => return true,
+            Entry::Action { action, .. } => Some(*action),
+            Entry::SubMenu { .. } => None,
+        };
+
+        if let Some(action) = action {
+            self.clicked.push(action);
+            self.open.clear();
+        } else {
+            // Clicking a menu in the menu bar a second time closes it again.
+            let was_open = self.open.get(depth) == Some(&i);
+            self.open.truncate(depth);
+            if !was_open || depth > 0 {
+                self.open.push(i);
+            }
+        }
+
+        true
+    }
+
+    /// Opens the menu under the mouse while another menu is open.
+    fn hover(&mut self) {
+        if self.open.is_empty() {
+            return;
+        }
+
+        let Some((depth, i)) = self.item_at(self.mouse) else {
+            return;
+        };
+
+        let is_menu = matches!(self.panels()[depth][i].1, Entry::SubMenu { .. });
+        if is_menu && self.open.get(depth) != Some(&i) {
+            self.open.truncate(depth);
+            self.open.push(i);
+        } else if !is_menu && depth > 0 {
+            self.open.truncate(depth);
+        }
+    }
+
+    fn draw_menu(&self, canvas: &mut WindowCanvas) {
+        let hovered = self.item_at(self.mouse);
+
+        canvas.set_draw_color(BACKGROUND);
+        let _ = canvas.fill_rect(Rect::new(0, 0, self.window_size.x, ROW_HEIGHT));
+        canvas.set_draw_color(BORDER);
+        let _ = canvas.draw_line(
+            Point::new(0, ROW_HEIGHT as i32 - 1),
+            Point::new(self.window_size.x as i32, ROW_HEIGHT as i32 - 1),
+        );
+
+        for (depth, panel) in self.panels().iter().enumerate() {
+            if depth > 0
+                && let Some(&(first, _)) = panel.first()
+            {
+                let height = panel.iter().map(|(rect, _)| rect.height()).sum();
+                let outline = Rect::new(first.x(), first.y(), first.width(), height);
+                canvas.set_draw_color(BACKGROUND);
+                let _ = canvas.fill_rect(outline);
+                canvas.set_draw_color(BORDER);
+                let _ = canvas.draw_rect(outline);
+            }
+
+            for (i, &(rect, entry)) in panel.iter().enumerate() {
+                if self.open.get(depth) == Some(&i) || hovered == Some((depth, i)) {
+                    canvas.set_draw_color(HIGHLIGHT);
+                    let _ = canvas.fill_rect(rect);
+                }
+
+                let position = ivec2(rect.x(), rect.y());
+                match entry {
+                    Entry::Divider => {
+                        let y = rect.center().y();
+                        canvas.set_draw_color(BORDER);
+                        let _ = canvas.draw_line(
+                            Point::new(rect.x() + PADDING as i32, y),
+                            Point::new(rect.right() - PADDING as i32, y),
+                        );
+                    }
+                    Entry::Action { title, .. } => draw_text(canvas, title, position, TEXT),
+                    Entry::SubMenu { title, .. } => {
+                        draw_text(canvas, title, position, TEXT);
+
+                        if depth > 0 {
+                            let arrow = ivec2(rect.right() - (PADDING * 3) as i32, rect.y());
+                            draw_text(canvas, ">", arrow, TEXT);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Platform for LinuxPlatform {
+    fn handle_event(&mut self, event: &Event) -> bool {
+        if let Event::Window {
+            win_event: WindowEvent::SizeChanged(width, height),
+            ..
+        } = event
+        {
+            self.window_size = uvec2(*width as u32, *height as u32);
+        }
+
+        if let Some(browser) = &mut self.browser {
+            if let Some(picked) = browser.handle_event(event, self.window_size) {
+                self.picked = picked.map(|path| (browser.dialog, path));
+                self.browser = None;
+            }
+
+            // The browser covers the whole window, but the window can still be
+            // closed.
+            return !matches!(event, Event::Window { .. });
+        }
+
+        match event {
+            Event::MouseMotion { x, y, .. } => {
+                self.mouse = ivec2(*x, *y);
+                self.hover();
+                false
+            }
+            Event::MouseButtonDown {
+                mouse_btn: MouseButton::Left,
+                x,
+                y,
+                ..
+            } => self.click(ivec2(*x, *y)),
+            Event::MouseButtonDown { .. } | Event::KeyDown { .. } if !self.open.is_empty() => {
+                self.open.clear();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn update(&mut self) {
+        for action in std::mem::take(&mut self.clicked) {
+            (self.actions[action])();
+        }
+    }
+
+    fn draw(&mut self, canvas: &mut WindowCanvas) {
+        self.window_size = canvas.window().size().into();
+        canvas.set_blend_mode(BlendMode::Blend);
+
+        self.draw_menu(canvas);
+        if let Some(browser) = &mut self.browser {
+            browser.draw(canvas, self.window_size);
+        }
+    }
+
+    fn menu_height(&self) -> u32 {
+        ROW_HEIGHT
+    }
+
+    fn show_file_dialog(&mut self, dialog: FileDialog, directory: &Path, extension: &str) {
+        self.open.clear();
+        self.browser = Some(FileBrowser::new(dialog, directory, extension));
+    }
+
+    fn picked_file(&mut self) -> Option<(FileDialog, PathBuf)> {
+        self.picked.take()
+    }
+
+    fn is_dialog_open(&self) -> bool {
+        self.browser.is_some()
+    }
+}
+
+/// A file dialog that is drawn on top of the window.
+struct FileBrowser {
+    dialog: FileDialog,
+    directory: PathBuf,
+    extension: String,
+    /// The name of every folder in the directory followed by the files with
+    /// the right extension, and whether the entry is a folder.
+    entries: Vec<(String, bool)>,
+    selected: usize,
+    /// The first entry that is shown in the list.
+    scroll: usize,
+    /// The name of the file that is saved to.
+    name: String,
+}
+
+/// Where the parts of the file browser are in the window.
+struct BrowserLayout {
+    panel: Rect,
+    list: Rect,
+    rows: usize,
+    name: Rect,
+    cancel: Rect,
+    confirm: Rect,
+}
+
+impl FileBrowser {
+    fn new(dialog: FileDialog, directory: &Path, extension: &str) -> Self {
+        // Relative paths run out of parents before the root of the file system.
+        let directory = fs::canonicalize(directory).unwrap_or_else(|_| directory.to_owned());
+
+        let mut browser = Self {
+            dialog,
+            directory,
+            extension: extension.to_owned(),
+            entries: Vec::new(),
+            selected: 0,
+            scroll: 0,
+            name: String::new(),
+        };
+
+        browser.read_directory();
+        browser
+    }
+
+    fn read_directory(&mut self) {
+        let mut folders = Vec::new();
+        let mut files = Vec::new();
+
+        for entry in fs::read_dir(&self.directory)
+            .into_iter()
+            .flatten()
+            .flatten()
+        {
+            let path = entry.path();
+            let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+                continue;
+            };
+
+            if path.is_dir() {
+                folders.push((name.to_owned(), true));
+            } else if path
+                .extension()
+                .is_some_and(|extension| extension == &*self.extension)
+            {
+                files.push((name.to_owned(), false));
+            }
+        }
+
+        folders.sort();
+        files.sort();
+
+        self.entries.clear();
+        if self.directory.parent().is_some() {
+            self.entries.push(("..".to_owned(), true));
+        }
+
+        self.entries.extend(folders.into_iter().chain(files));
+        self.selected = 0;
+        self.scroll = 0;
+    }
+
+    fn layout(&self, window_size: UVec2) -> BrowserLayout {
+        let size = window_size.as_ivec2();
+        let [row, padding] = [ROW_HEIGHT as i32, PADDING as i32];
+
+        let width = (size.x - 40).clamp(200, 720);
+        let height = (size.y - 40).clamp(200, 560);
+        let panel = Rect::new(
+            (size.x - width) / 2,
+            (size.y - height) / 2,
+            width as u32,
+            height as u32,
+        );
+
+        // The title and the directory are shown above the list, and the name of
+        // the file and the buttons below it.
+        let top = panel.y() + row * 2;
+        let buttons = panel.bottom() - row - padding;
+        let name = buttons - row - padding;
+        let bottom = match self.dialog {
+            FileDialog::Open => buttons - padding,
+            FileDialog::Save => name - padding,
+        };
+
+        let inner_width = panel.width() - PADDING * 2;
+        let list = Rect::new(
+            panel.x() + padding,
+            top,
+            inner_width,
+            (bottom - top).max(row) as u32,
+        );
+
+        let button_width = text_width("Cancel") + PADDING * 2;
+        let confirm = Rect::new(
+            panel.right() - padding - button_width as i32,
+            buttons,
+            button_width,
+            ROW_HEIGHT,
+        );
+        let cancel = Rect::new(
+            confirm.x() - padding - button_width as i32,
+            buttons,
+            button_width,
+            ROW_HEIGHT,
+        );
+
+        BrowserLayout {
+            panel,
+            list,
+            rows: (list.height() / ROW_HEIGHT) as usize,
+            name: Rect::new(panel.x() + padding, name, inner_width, ROW_HEIGHT),
+            cancel,
+            confirm,
+        }
+    }
+
+    /// Opens the selected folder, or returns the selected file.
+    fn activate(&mut self) -> Option<PathBuf> {
+        let (name, is_folder) = self.entries.get(self.selected)?.clone();
+        if !is_folder {
+            return Some(self.directory.join(name));
+        }
+
+        if name == ".." {
+            self.directory.pop();
+        } else {
+            self.directory.push(name);
+        }
+
+        self.read_directory();
+        None
+    }
+
+    /// Returns the file that is picked when the dialog is confirmed.
+    fn confirm(&mut self) -> Option<PathBuf> {
+        if self.dialog == FileDialog::Save && !self.name.is_empty() {
+            let extension = format!(".{}", self.extension);
+            let name = match self.name.ends_with(&extension) {
+                true => self.name.clone(),
+                false => self.name.clone() + &extension,
+            };
+
+            return Some(self.directory.join(name));
+        }
+
+        self.activate()
+    }
+
+    /// Returns what was picked once the browser should be closed, where
+    /// nothing means that the dialog was cancelled.
+    fn handle_event(&mut self, event: &Event, window_size: UVec2) -> Option<Option<PathBuf>> {
+        let last = self.entries.len().saturating_sub(1);
+
+        match event {
+            Event::KeyDown {
+                keycode: Some(keycode),
+                ..
+            } => match keycode {
+                Keycode::Escape => return Some(None),
+                Keycode::Up => self.selected = self.selected.saturating_sub(1),
+                Keycode::Down => self.selected = (self.selected + 1).min(last),
+                Keycode::Return | Keycode::KpEnter => return self.confirm().map(Some),
+                // Backspace goes up a folder once the name is empty.
+                Keycode::Backspace if self.name.pop().is_none() && self.directory.pop() => {
+                    self.read_directory();
+                }
+                _ => {}
+            },
+            Event::TextInput { text, .. } if self.dialog == FileDialog::Save => {
+                // Only characters the font can show are allowed in the name.
+                let text = text.chars().filter(|c| font::glyph(*c).is_some());
+                self.name.extend(text);
+            }
+            Event::MouseWheel { y, .. } => {
+                let selected = self.selected as i32 - y;
+                self.selected = selected.clamp(0, last as i32) as usize;
+            }
+            Event::MouseButtonDown {
+                mouse_btn: MouseButton::Left,
+                x,
+                y,
+                ..
+            } => {
+                let layout = self.layout(window_size);
+                let point = Point::new(*x, *y);
+
+                if layout.cancel.contains_point(point) {
+                    return Some(None);
+                }
+
+                if layout.confirm.contains_point(point) {
+                    return self.confirm().map(Some);
+                }
+
+                if layout.list.contains_point(point) {
+                    let row = (y - layout.list.y()) as usize / ROW_HEIGHT as usize;
+                    let i = self.scroll + row;
+
+                    // Clicking the selected entry a second time opens it.
+                    if i == self.selected {
+                        return self.activate().map(Some);
+                    } else if i < self.entries.len() {
+                        self.selected = i;
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        None
+    }
+
+    fn draw(&mut self, canvas: &mut WindowCanvas, window_size: UVec2) {
+        let layout = self.layout(window_size);
+
+        // Keep the selection in view.
+        let rows = layout.rows.max(1);
+        self.scroll = self.scroll.min(self.selected);
+        self.scroll = self.scroll.max((self.selected + 1).saturating_sub(rows));
+
+        canvas.set_draw_color(Color::RGBA(0, 0, 0, 120));
+        let _ = canvas.fill_rect(None);
+
+        canvas.set_draw_color(BACKGROUND);
+        let _ = canvas.fill_rect(layout.panel);
+        canvas.set_draw_color(BORDER);
+        let _ = canvas.draw_rect(layout.panel);
+
+        let (title, confirm) = match self.dialog {
+            FileDialog::Open => ("Open File", "Open"),
+            FileDialog::Save => ("Save File", "Save"),
+        };
+
+        let x = layout.panel.x();
+        let y = layout.panel.y();
+        draw_text(canvas, title, ivec2(x, y), TEXT);
+
+        // Long paths are cut from the start, where they are the least useful.
+        let advance = (font::GLYPH_WIDTH + font::GLYPH_SPACING) * SCALE;
+        let fits = ((layout.list.width() / advance) as usize).saturating_sub(3);
+        let directory = self.directory.to_string_lossy();
+        let count = directory.chars().count();
+        let directory = match count > fits {
+            true => format!(
+                "...{}",
+                directory.chars().skip(count - fits).collect::<String>()
+            ),
+            false => directory.into_owned(),
+        };
+
+        draw_text(
+            canvas,
+            &directory,
+            ivec2(x, y + ROW_HEIGHT as i32),
+            DIMMED_TEXT,
+        );
+
+        canvas.set_draw_color(Color::WHITE);
+        let _ = canvas.fill_rect(layout.list);
+
+        let shown = self.entries.iter().enumerate().skip(self.scroll).take(rows);
+        for (row, (i, (name, is_folder))) in shown.enumerate() {
+            let y = layout.list.y() + (row as u32 * ROW_HEIGHT) as i32;
+            let rect = Rect::new(layout.list.x(), y, layout.list.width(), ROW_HEIGHT);
+
+            if i == self.selected {
+                canvas.set_draw_color(HIGHLIGHT);
+                let _ = canvas.fill_rect(rect);
+            }
+
+            let name = match is_folder {
+                true => format!("{name}/"),
+                false => name.clone(),
+            };
+
+            draw_text(canvas, &name, ivec2(rect.x(), rect.y()), TEXT);
+        }
+
+        canvas.set_draw_color(BORDER);
+        let _ = canvas.draw_rect(layout.list);
+
+        if self.dialog == FileDialog::Save {
+            canvas.set_draw_color(Color::WHITE);
+            let _ = canvas.fill_rect(layout.name);
+            canvas.set_draw_color(BORDER);
+            let _ = canvas.draw_rect(layout.name);
+
+            let name = format!("{}_", self.name);
+            let position = ivec2(layout.name.x(), layout.name.y());
+            draw_text(canvas, &name, position, TEXT);
+        }
+
+        for (rect, label) in [(layout.cancel, "Cancel"), (layout.confirm, confirm)] {
+            canvas.set_draw_color(HIGHLIGHT);
+            let _ = canvas.fill_rect(rect);
+            canvas.set_draw_color(BORDER);
+            let _ = canvas.draw_rect(rect);
+
+            let x = rect.x() + (rect.width() - text_width(label)) as i32 / 2 - PADDING as i32;
+            draw_text(canvas, label, ivec2(x, rect.y()), TEXT);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ::sdl2::keyboard::Mod;
+
+    use super::*;
+
+    /// A menu bar with File and Edit menus. The actions are numbered in the
+    /// order they are listed.
+    fn platform() -> LinuxPlatform {
+        let action = |title| MenuItem::Action {
+            title,
+            action: Box::new(|| {}),
+        };
+
+        let menu = vec![
+            MenuItem::SubMenu {
+                title: "File",
+                items: vec![
+                    action("New"),
+                    MenuItem::Divider,
+                    action("Open"),
+                    MenuItem::SubMenu {
+                        title: "Recent",
+                        items: vec![action("Level 1")],
+                    },
+                ],
+            },
+            MenuItem::SubMenu {
+                title: "Edit",
+                items: vec![action("Undo")],
+            },
+        ];
+
+        let mut actions = Vec::new();
+        let menu = Entry::from_items(menu, &mut actions);
+
+        LinuxPlatform {
+            menu,
+            actions,
+            open: Vec::new(),
+            clicked: Vec::new(),
+            mouse: IVec2::ZERO,
+            window_size: uvec2(800, 600),
+            browser: None,
+            picked: None,
+        }
+    }
+
+    fn center(rect: Rect) -> IVec2 {
+        ivec2(rect.center().x(), rect.center().y())
+    }
+
+    /// Returns the center of the item at `index` in the panel at `depth`.
+    fn item(platform: &LinuxPlatform, depth: usize, index: usize) -> IVec2 {
+        center(platform.panels()[depth][index].0)
+    }
+
+    fn key(keycode: Keycode) -> Event {
+        Event::KeyDown {
+            timestamp: 0,
+            window_id: 0,
+            keycode: Some(keycode),
+            scancode: None,
+            keymod: Mod::NOMOD,
+            repeat: false,
+        }
+    }
+
+    fn text(text: &str) -> Event {
+        Event::TextInput {
+            timestamp: 0,
+            window_id: 0,
+            text: text.to_owned(),
+        }
+    }
+
+    /// Creates a folder with a sub folder, two levels and a file the browser
+    /// should leave out.
+    fn test_directory(name: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("mario_test_browser_{name}"));
+        let _ = fs::remove_dir_all(&directory);
+
+        fs::create_dir_all(directory.join("sub")).unwrap();
+        for file in ["b.level", "a.level", "notes.txt"] {
+            fs::write(directory.join(file), "").unwrap();
+        }
+
+        fs::canonicalize(directory).unwrap()
+    }
+
+    #[test]
+    fn test_menu_bar_items_are_laid_out_left_to_right() {
+        let platform = platform();
+        let bar = &platform.panels()[0];
+        assert_eq!(bar.len(), 2);
+        assert_eq!(bar[0].0.right(), bar[1].0.x());
+
+        assert_eq!(platform.item_at(item(&platform, 0, 0)), Some((0, 0)));
+        assert_eq!(platform.item_at(item(&platform, 0, 1)), Some((0, 1)));
+        assert_eq!(platform.item_at(ivec2(10, 100)), None);
+    }
+
+    #[test]
+    fn test_menus_open_and_close_when_clicked() {
+        let mut platform = platform();
+        assert!(platform.click(item(&platform, 0, 0)));
+        assert_eq!(platform.open, [0]);
+
+        // The menu opens below the menu bar.
+        let panels = platform.panels();
+        assert_eq!(panels.len(), 2);
+        assert_eq!(panels[1].len(), 4);
+        assert_eq!(panels[1][0].0.y(), ROW_HEIGHT as i32);
+
+        assert!(platform.click(item(&platform, 0, 0)));
+        assert!(platform.open.is_empty());
+    }
+
+    #[test]
+    fn test_clicking_an_action_picks_it() {
+        let mut platform = platform();
+        platform.click(item(&platform, 0, 0));
+
+        // Dividers can't be picked and keep the menu open.
+        assert!(platform.click(item(&platform, 1, 1)));
+        assert_eq!(platform.open, [0]);
+
+        assert!(platform.click(item(&platform, 1, 2)));
+        assert_eq!(platform.clicked, [1]);
+        assert!(platform.open.is_empty());
+    }
+
+    #[test]
+    fn test_sub_menus_open_to_the_right() {
+        let mut platform = platform();
+        platform.click(item(&platform, 0, 0));
+        platform.click(item(&platform, 1, 3));
+        assert_eq!(platform.open, [0, 3]);
+
+        let panels = platform.panels();
+        assert_eq!(panels[2][0].0.x(), panels[1][3].0.right());
+        assert_eq!(panels[2][0].0.y(), panels[1][3].0.y());
+
+        platform.click(item(&platform, 2, 0));
+        assert_eq!(platform.clicked, [2]);
+    }
+
+    #[test]
+    fn test_clicking_elsewhere_closes_the_menus() {
+        let mut platform = platform();
+        assert!(!platform.click(ivec2(400, 300)));
+
+        // The empty part of the menu bar still belongs to the menu.
+        assert!(platform.click(ivec2(400, 5)));
+
+        platform.click(item(&platform, 0, 1));
+        assert!(platform.click(ivec2(400, 300)));
+        assert!(platform.open.is_empty());
+        assert!(platform.clicked.is_empty());
+    }
+
+    #[test]
+    fn test_browser_lists_folders_before_files() {
+        let directory = test_directory("list");
+        let browser = FileBrowser::new(FileDialog::Open, &directory, "level");
+
+        let entries: Vec<_> = browser
+            .entries
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect();
+        assert_eq!(entries, ["..", "sub", "a.level", "b.level"]);
+        assert_eq!(browser.selected, 0);
+    }
+
+    #[test]
+    fn test_browser_opens_folders_and_files() {
+        let directory = test_directory("open");
+        let mut browser = FileBrowser::new(FileDialog::Open, &directory, "level");
+
+        browser.selected = 1;
+        assert_eq!(browser.activate(), None);
+        assert_eq!(browser.directory, directory.join("sub"));
+        assert_eq!(browser.entries, [("..".to_owned(), true)]);
+
+        assert_eq!(browser.activate(), None);
+        assert_eq!(browser.directory, directory);
+
+        browser.selected = 2;
+        assert_eq!(browser.confirm(), Some(directory.join("a.level")));
+    }
+
+    #[test]
+    fn test_browser_is_navigated_with_keys() {
+        let directory = test_directory("keys");
+        let mut browser = FileBrowser::new(FileDialog::Open, &directory, "level");
+        let size = uvec2(800, 600);
+
+        assert_eq!(browser.handle_event(&key(Keycode::Up), size), None);
+        assert_eq!(browser.selected, 0);
+
+        for _ in 0..10 {
+            browser.handle_event(&key(Keycode::Down), size);
+        }
+
+        assert_eq!(browser.selected, 3);
+        assert_eq!(
+            browser.handle_event(&key(Keycode::Return), size),
+            Some(Some(directory.join("b.level")))
+        );
+
+        assert_eq!(
+            browser.handle_event(&key(Keycode::Escape), size),
+            Some(None)
+        );
+    }
+
+    #[test]
+    fn test_saved_files_get_the_extension() {
+        let directory = test_directory("save");
+        let mut browser = FileBrowser::new(FileDialog::Save, &directory, "level");
+        let size = uvec2(800, 600);
+
+        browser.handle_event(&text("new"), size);
+        assert_eq!(
+            browser.handle_event(&key(Keycode::Return), size),
+            Some(Some(directory.join("new.level")))
+        );
+
+        browser.handle_event(&text(".level"), size);
+        assert_eq!(browser.confirm(), Some(directory.join("new.level")));
+    }
+
+    #[test]
+    fn test_backspace_goes_up_once_the_name_is_empty() {
+        let directory = test_directory("backspace");
+        let mut browser = FileBrowser::new(FileDialog::Save, &directory.join("sub"), "level");
+        let size = uvec2(800, 600);
+
+        browser.handle_event(&text("a"), size);
+        browser.handle_event(&key(Keycode::Backspace), size);
+        assert!(browser.name.is_empty());
+        assert_eq!(browser.directory, directory.join("sub"));
+
+        browser.handle_event(&key(Keycode::Backspace), size);
+        assert_eq!(browser.directory, directory);
+        assert_eq!(browser.entries.len(), 4);
+    }
+}
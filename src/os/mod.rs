@@ -1,3 +1,19 @@
+//! Everything the editor needs from the platform it runs on: a window with a
+//! menu bar and dialogs for picking files.
+//!
+//! Windows uses the native menu bar and file dialogs. Every other platform gets
+//! a menu bar and a file browser that are drawn inside the window with SDL.
+
+use std::path::{Path, PathBuf};
+
+use ::glam::*;
+use ::sdl2::event::Event;
+use ::sdl2::render::WindowCanvas;
+use ::sdl2::VideoSubsystem;
+
+#[cfg(not(windows))]
+pub mod linux;
+#[cfg(windows)]
 pub mod windows;
 
 pub enum MenuItem<'a> {
@@ -12,8 +28,62 @@ pub enum MenuItem<'a> {
     },
 }
 
-pub struct Menu(windows::Menu);
+/// What a file dialog picks a file for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileDialog {
+    /// Picks a file that already exists.
+    Open,
+    /// Picks a file to write to, which doesn't have to exist yet.
+    Save,
+}
+
+/// The parts of the editor that work differently on each platform.
+pub trait Platform {
+    /// Handles `event` if it was meant for the menu bar or an open dialog.
+    ///
+    /// Returns whether the event was handled, in which case the editor should
+    /// ignore it.
+    fn handle_event(&mut self, event: &Event) -> bool;
+
+    /// Runs the actions of the menu items that have been picked since the last
+    /// update.
+    fn update(&mut self);
+
+    /// Draws whatever the platform draws inside the window on top of the
+    /// editor.
+    fn draw(&mut self, canvas: &mut WindowCanvas);
+
+    /// Returns how much of the top of the window is covered by the menu bar.
+    fn menu_height(&self) -> u32;
+
+    /// Asks the user for a file ending in `extension`, starting out in
+    /// `directory`.
+    ///
+    /// Dialogs may stay open over several frames, so the file is returned from
+    /// `picked_file` instead.
+    fn show_file_dialog(&mut self, dialog: FileDialog, directory: &Path, extension: &str);
+
+    /// Returns the file that was picked once a file dialog has closed. Nothing
+    /// is returned when the dialog was cancelled.
+    fn picked_file(&mut self) -> Option<(FileDialog, PathBuf)>;
+
+    /// Returns whether a dialog is open, which keeps the editor from reacting
+    /// to input.
+    fn is_dialog_open(&self) -> bool;
+}
+
+/// Creates a window of `size` with `menu` as its menu bar, along with the
+/// platform the window runs on.
+pub fn new_window(
+    video: &VideoSubsystem,
+    title: &str,
+    size: UVec2,
+    menu: Vec<MenuItem>,
+) -> (Box<dyn Platform>, sdl2::video::Window) {
+    #[cfg(windows)]
+    let (platform, window) = windows::new_window(video, title, size, menu);
+    #[cfg(not(windows))]
+    let (platform, window) = linux::new_window(video, title, size, menu);
 
-pub fn new_menu(items: Vec<MenuItem>) -> Menu {
-    Menu(windows::new_menu(items))
+    (Box::new(platform), window)
 }
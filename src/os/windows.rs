@@ -1,29 +1,49 @@
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+
+use glam::*;
+use sdl2::event::Event;
+use sdl2::render::WindowCanvas;
 use sdl2::{video, VideoSubsystem};
 use windows::core::*;
 use windows::w;
 use windows::Win32::Foundation::*;
 use windows::Win32::Graphics::Gdi::*;
+use windows::Win32::System::Com::*;
 use windows::Win32::System::LibraryLoader::*;
+use windows::Win32::UI::Shell::Common::*;
+use windows::Win32::UI::Shell::*;
 use windows::Win32::UI::WindowsAndMessaging::*;
 
-use crate::os::MenuItem;
+use crate::os::{FileDialog, MenuItem, Platform};
+
+thread_local! {
+    /// The ids of the menu items that have been clicked since the last update,
+    /// which are collected by the window procedure.
+    static CLICKED: RefCell<Vec<u32>> = RefCell::new(Vec::new());
+}
 
 pub struct Menu {
     handle: HMENU,
+    /// The action of every item, where the item with id `n` runs the action at
+    /// index `n - 1`.
+    actions: Vec<Box<dyn Fn()>>,
 }
 
 pub fn new_menu(items: Vec<MenuItem>) -> Menu {
-    fn new_menu_recursive(parent: HMENU, items: Vec<MenuItem>, id: &mut u32) {
+    fn new_menu_recursive(parent: HMENU, items: Vec<MenuItem>, actions: &mut Vec<Box<dyn Fn()>>) {
         for (i, item) in items.into_iter().enumerate() {
             match item {
                 MenuItem::Action { title, action } => {
+                    actions.push(action);
+
                     let title = title.encode_utf16().chain([0]).collect::<Vec<_>>();
                     let item = MENUITEMINFOW {
                         cbSize: std::mem::size_of::<MENUITEMINFOW>() as _,
                         fMask: MENU_ITEM_MASK(0) | MIIM_ID | MIIM_STRING,
                         fType: MFT_STRING,
                         fState: MENU_ITEM_STATE(0),
-                        wID: *id,
+                        wID: actions.len() as u32,
                         hSubMenu: HMENU::default(),
                         hbmpChecked: HBITMAP::default(),
                         hbmpUnchecked: HBITMAP::default(),
@@ -33,15 +53,22 @@ pub fn new_menu(items: Vec<MenuItem>) -> Menu {
                         hbmpItem: HBITMAP::default(),
                     };
 
-                    *id += 1;
+                    unsafe { InsertMenuItemW(parent, i as _, true, &item) };
+                }
+                MenuItem::Divider => {
+                    let item = MENUITEMINFOW {
+                        cbSize: std::mem::size_of::<MENUITEMINFOW>() as _,
+                        fMask: MIIM_FTYPE,
+                        fType: MFT_SEPARATOR,
+                        ..Default::default()
+                    };
 
                     unsafe { InsertMenuItemW(parent, i as _, true, &item) };
                 }
-                MenuItem::Divider => {}
                 MenuItem::SubMenu { title, items } => {
                     // Handle submenu
                     let sub = unsafe { CreateMenu().unwrap() };
-                    new_menu_recursive(sub, items, id);
+                    new_menu_recursive(sub, items, actions);
 
                     let title = title.encode_utf16().chain([0]).collect::<Vec<_>>();
                     let item = MENUITEMINFOW {
@@ -65,11 +92,11 @@ pub fn new_menu(items: Vec<MenuItem>) -> Menu {
         }
     }
 
-    let mut id = 1;
+    let mut actions = Vec::new();
     let handle = unsafe { CreateMenu().unwrap() };
-    new_menu_recursive(handle, items, &mut id);
+    new_menu_recursive(handle, items, &mut actions);
 
-    Menu { handle }
+    Menu { handle, actions }
 }
 
 pub struct Window {
@@ -79,6 +106,13 @@ pub struct Window {
 
 impl Window {
     unsafe extern "system" fn wndproc(hwnd: HWND, msg: u32, wp: WPARAM, lp: LPARAM) -> LRESULT {
+        // SDL passes the messages it doesn't handle itself on to us. Menu items
+        // send their id in the low word, with a high word of zero.
+        if msg == WM_COMMAND && (wp.0 >> 16) & 0xffff == 0 {
+            CLICKED.with(|clicked| clicked.borrow_mut().push((wp.0 & 0xffff) as u32));
+            return LRESULT(0);
+        }
+
         DefWindowProcW(hwnd, msg, wp, lp)
     }
 
@@ -116,8 +150,6 @@ impl Window {
             )
         };
 
-        println!("got here");
-
         Self { instance, handle }
     }
 
@@ -131,3 +163,119 @@ impl Window {
         unsafe { video::Window::from_ll(subsystem, raw) }
     }
 }
+
+pub fn new_window(
+    video: &VideoSubsystem,
+    title: &str,
+    size: UVec2,
+    menu: Vec<MenuItem>,
+) -> (WindowsPlatform, video::Window) {
+    // The file dialogs are COM objects.
+    let _ = unsafe { CoInitializeEx(None, COINIT_APARTMENTTHREADED) };
+
+    let mut window = Window::new(title);
+    let menu = new_menu(menu);
+    window.set_menu(&menu);
+
+    let mut window = window.into_sdl2(video.clone());
+    window.show();
+    window.set_size(size.x, size.y).unwrap();
+
+    let platform = WindowsPlatform { menu, picked: None };
+    (platform, window)
+}
+
+/// Shows a native file dialog and blocks until the user has closed it.
+unsafe fn show_file_dialog(
+    dialog: FileDialog,
+    directory: &Path,
+    extension: &str,
+) -> Result<PathBuf> {
+    let class = match dialog {
+        FileDialog::Open => &FileOpenDialog,
+        FileDialog::Save => &FileSaveDialog,
+    };
+
+    let file_dialog: IFileDialog = CoCreateInstance(class, None, CLSCTX_INPROC_SERVER)?;
+
+    let spec: Vec<_> = format!("*.{extension}").encode_utf16().chain([0]).collect();
+    file_dialog.SetFileTypes(&[COMDLG_FILTERSPEC {
+        pszName: PCWSTR::from_raw(spec.as_ptr()),
+        pszSpec: PCWSTR::from_raw(spec.as_ptr()),
+    }])?;
+
+    let extension: Vec<_> = extension.encode_utf16().chain([0]).collect();
+    file_dialog.SetDefaultExtension(PCWSTR::from_raw(extension.as_ptr()))?;
+
+    // The dialog falls back to the last folder it was in when the directory
+    // can't be found.
+    let directory = std::env::current_dir().unwrap_or_default().join(directory);
+    let directory: Vec<_> = directory
+        .as_os_str()
+        .to_string_lossy()
+        .encode_utf16()
+        .chain([0])
+        .collect();
+
+    let folder = SHCreateItemFromParsingName::<_, _, IShellItem>(
+        PCWSTR::from_raw(directory.as_ptr()),
+        None::<&IBindCtx>,
+    );
+
+    if let Ok(folder) = folder {
+        file_dialog.SetFolder(&folder)?;
+    }
+
+    file_dialog.Show(HWND::default())?;
+
+    let item = file_dialog.GetResult()?;
+    let path = item.GetDisplayName(SIGDN_FILESYSPATH)?;
+    Ok(PathBuf::from(path.to_string()?))
+}
+
+/// Uses the native menu bar and file dialogs of Windows.
+pub struct WindowsPlatform {
+    menu: Menu,
+    picked: Option<(FileDialog, PathBuf)>,
+}
+
+impl Platform for WindowsPlatform {
+    fn handle_event(&mut self, _event: &Event) -> bool {
+        false
+    }
+
+    fn update(&mut self) {
+        let clicked = CLICKED.with(|clicked| std::mem::take(&mut *clicked.borrow_mut()));
+        for id in clicked {
+            let action = (id as usize)
+                .checked_sub(1)
+                .and_then(|i| self.menu.actions.get(i));
+            if let Some(action) = action {
+                action();
+            }
+        }
+    }
+
+    fn draw(&mut self, _canvas: &mut WindowCanvas) {}
+
+    fn menu_height(&self) -> u32 {
+        // The menu bar is outside of the part of the window that SDL draws to.
+        0
+    }
+
+    fn show_file_dialog(&mut self, dialog: FileDialog, directory: &Path, extension: &str) {
+        // Cancelling the dialog is reported as an error as well.
+        if let Ok(path) = unsafe { show_file_dialog(dialog, directory, extension) } {
+            self.picked = Some((dialog, path));
+        }
+    }
+
+    fn picked_file(&mut self) -> Option<(FileDialog, PathBuf)> {
+        self.picked.take()
+    }
+
+    fn is_dialog_open(&self) -> bool {
+        // Native dialogs block until they are closed.
+        false
+    }
+}
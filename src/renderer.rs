@@ -1,29 +1,95 @@
 use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
+use std::rc::Rc;
 
 use ::glam::*;
-use ::image::*;
 use ::sdl2::pixels::{Color, PixelFormat, PixelFormatEnum};
 use ::sdl2::rect::{Point, Rect};
 use ::sdl2::render::*;
-use ::sdl2::video::*;
+use ::sdl2::video::{FullscreenType, WindowContext};
+use ::serde::{Deserialize, Serialize};
 
+use crate::animation::*;
+use crate::assets::*;
+use crate::atlas::*;
 use crate::map::*;
-use crate::scene;
+use crate::menu::*;
 use crate::scene::*;
+use crate::{font, scene};
 
 pub struct Renderer {
     pub canvas: WindowCanvas,
+    assets: AssetManager,
+    /// The sprite sheets that were drawn in the last frame, which are kept
+    /// loaded until they are no longer drawn.
+    textures: HashMap<PathBuf, Handle<Texture>>,
+    /// The sprite sheets that have been drawn since the last frame was
+    /// presented.
+    drawn: HashSet<PathBuf>,
+    /// The background images that couldn't be loaded, which are only reported
+    /// once.
+    missing_backgrounds: HashSet<PathBuf>,
+    atlases: Atlases,
+    animations: Animations,
+    tile_table: TileTable,
+    /// Creates the textures the tiles are drawn into.
     creator: TextureCreator<WindowContext>,
-    cache: HashMap<&'static str, (Texture, usize)>,
+    /// The chunks of each tile layer in the scene.
+    chunks: Vec<Option<TileChunks>>,
+}
+
+/// How the game is shown on the screen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct VideoSettings {
+    /// The size of the window when it isn't fullscreen.
+    pub window_size: UVec2,
+    pub fullscreen: bool,
+    /// Only scales the screen up by whole numbers, so that every pixel has the
+    /// same size. The rest of the window is filled with black bars.
+    pub integer_scaling: bool,
+}
+
+impl Default for VideoSettings {
+    fn default() -> Self {
+        Self {
+            window_size: Renderer::SCREEN_SIZE.as_uvec2() * 3,
+            fullscreen: false,
+            integer_scaling: true,
+        }
+    }
+}
+
+/// The tiles of a layer drawn into textures of `TILES` x `TILES` tiles each.
+struct TileChunks {
+    /// The tiles the chunks were drawn from.
+    tiles: Vec<MapTile>,
+    /// The sprite sheets the chunks were drawn with.
+    sheets: Vec<(PathBuf, Rc<Texture>)>,
+    chunks: HashMap<IVec2, Texture>,
+}
+
+impl TileChunks {
+    const TILES: u32 = 16;
+
+    fn destroy(self) {
+        for (_, texture) in self.chunks {
+            unsafe { texture.destroy() };
+        }
+    }
 }
 
 impl Renderer {
     pub const TILES_X: u32 = 25;
     pub const TILES_Y: u32 = 18;
     pub const TILE_SIZE: u32 = 16;
+    /// The size of the part of the world that is shown at once.
+    pub const SCREEN_SIZE: Vec2 = Vec2::new(
+        (Self::TILES_X * Self::TILE_SIZE) as f32,
+        (Self::TILES_Y * Self::TILE_SIZE) as f32,
+    );
 
-    pub fn new(mut canvas: WindowCanvas) -> Renderer {
+    pub fn new(mut canvas: WindowCanvas, assets: AssetManager) -> Renderer {
         canvas.set_blend_mode(BlendMode::Blend);
 
         canvas
@@ -35,24 +101,110 @@ impl Renderer {
 
         let creator = canvas.texture_creator();
 
+        let atlases = Atlases::load_dir(&Atlases::DIR).unwrap_or_else(|error| {
+            panic!(
+                "The sprite atlases in {} could not be loaded: {error}",
+                Atlases::DIR
+            )
+        });
+
+        let animations = Animations::load(&Animations::PATH).unwrap_or_else(|error| {
+            panic!(
+                "The animations in {} could not be loaded: {error}",
+                Animations::PATH
+            )
+        });
+
+        let tile_table = TileTable::load(&TileTable::PATH).unwrap_or_else(|error| {
+            panic!(
+                "The tile table in {} could not be loaded: {error}",
+                TileTable::PATH
+            )
+        });
+
+        // Catch animations and tiles that refer to missing frames before they are
+        // drawn.
+        for sprite in animations.sprites().chain(tile_table.sprites()) {
+            if atlases.frame(&sprite.atlas, &sprite.frame).is_none() {
+                panic!(
+                    "The sprites refer to the missing frame {}/{}",
+                    sprite.atlas, sprite.frame
+                );
+            }
+        }
+
         Renderer {
             canvas,
+            assets,
+            textures: HashMap::new(),
+            drawn: HashSet::new(),
+            missing_backgrounds: HashSet::new(),
+            atlases,
+            animations,
+            tile_table,
             creator,
-            cache: HashMap::with_capacity(128),
+            chunks: Vec::new(),
+        }
+    }
+
+    /// Changes the window and how the screen is scaled to fit it.
+    ///
+    /// The screen always keeps its aspect ratio, so any space left over in the
+    /// window is filled with black bars.
+    pub fn apply_settings(&mut self, video: &VideoSettings) {
+        self.canvas
+            .set_integer_scale(video.integer_scaling)
+            .unwrap();
+
+        let window = self.canvas.window_mut();
+        let fullscreen = if video.fullscreen {
+            FullscreenType::Desktop
+        } else {
+            FullscreenType::Off
+        };
+
+        if let Err(error) = window.set_fullscreen(fullscreen) {
+            eprintln!("The window could not be changed to {fullscreen:?}: {error}");
+        }
+
+        if !video.fullscreen && window.size() != video.window_size.into() {
+            let [width, height] = video.window_size.max(UVec2::ONE).to_array();
+            if let Err(error) = window.set_size(width, height) {
+                eprintln!("The window could not be resized: {error}");
+            }
         }
     }
 
-    pub fn update(&mut self, scene: &mut Scene) {
+    /// Clears the screen before drawing a new frame.
+    pub fn clear(&mut self) {
         self.canvas.set_draw_color(Color::BLACK);
         self.canvas.clear();
+    }
+
+    /// Shows everything that has been drawn since the last call to `clear`.
+    pub fn present(&mut self) {
+        self.canvas.present();
 
+        // Sprite sheets that are no longer drawn are let go of, so the asset
+        // manager can evict them.
+        let drawn = std::mem::take(&mut self.drawn);
+        self.textures.retain(|path, _| drawn.contains(path));
+    }
+
+    pub fn draw_scene(&mut self, scene: &mut Scene) {
         // self.move_camera(scene, scene.camera.position);
         self.draw_background(scene::Rgba::from(scene.background.as_vec3() / 255.0));
-        self.draw_tiles(scene);
+        self.draw_background_layers(scene);
+        self.draw_tiles(scene, false);
 
         for enemy in &scene.enemies {
-            let sprite = enemy.to_sprite();
-            self.draw_image(&scene.camera, &sprite, enemy.position.as_uvec2(), 1);
+            if let Some(sprite) = self.animations.sprite(&enemy.animator) {
+                self.draw_image(&scene.camera, &sprite, enemy.position, 1);
+            }
+        }
+
+        if scene.player_behind {
+            self.draw_player(scene);
         }
 
         for entity in &scene.entities {
@@ -61,78 +213,306 @@ impl Renderer {
         }
 
         // self.draw_sprites(scene);
-        self.draw_player(scene);
+        if !scene.player_behind {
+            self.draw_player(scene);
+        }
 
-        self.canvas.present();
+        self.draw_tiles(scene, true);
+
+        if let Some(cover) = &scene.cover {
+            self.draw_cover(&scene.camera, cover);
+        }
     }
 
-    pub fn draw_tiles(&mut self, scene: &mut Scene) {
-        for MapTile { block, coordinate } in &scene.tiles {
-            let sprite = block.to_sprite();
-            self.draw_image(&scene.camera, &sprite, *coordinate * 16, 1);
+    /// Draws `cover` over everything that has been drawn so far.
+    pub fn draw_cover(&mut self, camera: &Camera, cover: &ScreenCover) {
+        self.canvas.set_draw_color(Color::BLACK);
+
+        match cover {
+            ScreenCover::Fade(alpha) => {
+                let alpha = (alpha.clamp(0.0, 1.0) * 255.0) as u8;
+                self.canvas.set_draw_color(Color::RGBA(0, 0, 0, alpha));
+                self.canvas.fill_rect(None).unwrap();
+            }
+            ScreenCover::Iris { center, radius } => {
+                let center = *center - camera.position;
+                let size = camera.size.as_ivec2();
+
+                // Every row of the screen is covered on both sides of the circle.
+                let mut rects = Vec::with_capacity(size.y as usize * 2);
+                for y in 0..size.y {
+                    let dy = y as f32 + 0.5 - center.y;
+                    let half_width = (radius * radius - dy * dy).max(0.0).sqrt();
+                    let left = (center.x - half_width).round() as i32;
+                    let right = (center.x + half_width).round() as i32;
+
+                    if left > 0 {
+                        rects.push(Rect::new(0, y, left as u32, 1));
+                    }
+
+                    if right < size.x {
+                        rects.push(Rect::new(right, y, (size.x - right) as u32, 1));
+                    }
+                }
+
+                self.canvas.fill_rects(&rects).unwrap();
+            }
+            ScreenCover::TitleCard(title) => {
+                const SCALE: u32 = 2;
+
+                self.canvas.fill_rect(None).unwrap();
+
+                let size = Self::SCREEN_SIZE.as_ivec2();
+                let width = (font::text_width(title) * SCALE) as i32;
+                let height = (font::GLYPH_HEIGHT * SCALE) as i32;
+                let position = ivec2(size.x - width, size.y - height) / 2;
+                self.draw_text(title, position, SCALE, Color::WHITE);
+            }
         }
     }
 
-    pub fn draw_image(&mut self, camera: &Camera, sprite: &Sprite, mut position: UVec2, size: u32) {
-        let Self {
-            cache,
-            canvas,
-            creator,
-        } = self;
+    pub fn draw_background_layers(&mut self, scene: &Scene) {
+        for layer in &scene.backgrounds {
+            self.drawn.insert(layer.image.clone());
+            let texture = match self.textures.get(&layer.image) {
+                Some(texture) => texture,
+                // Background images use their own alpha channel for transparency.
+                None => match self.assets.texture(&layer.image, &[]) {
+                    Ok(texture) => self.textures.entry(layer.image.clone()).or_insert(texture),
+                    Err(error) => {
+                        // Levels can refer to images that don't exist, which are left out.
+                        if self.missing_backgrounds.insert(layer.image.clone()) {
+                            eprintln!(
+                                "The background {} could not be loaded: {error}",
+                                layer.image.display()
+                            );
+                        }
 
-        let Sprite {
-            asset_path,
-            bounding_box,
-            mirror,
-        } = sprite;
+                        continue;
+                    }
+                },
+            };
 
-        let texture = if let Some((texture, _)) = cache.get(asset_path) {
-            texture
-        } else {
-            let image = image::open(asset_path).unwrap();
-            let image = image.to_rgba8();
-
-            let pixels: Vec<_> = image
-                .pixels()
-                .flat_map(|color| {
-                    // Both #00298C and #9290FF are used as background color on the tilesheets. This
-                    // should just be made transparent. Since we cache the texture, we
-                    // only pay the price of clearing these pixels once.
-                    if color != &Rgba([0, 41, 140, 255]) && color != &Rgba([146, 144, 255, 255]) {
-                        color.0
-                    } else {
-                        [0, 0, 0, 0]
+            let texture = texture.get();
+            let query = texture.query();
+            let size = ivec2(query.width as i32, query.height as i32);
+
+            // An empty image can't be repeated, and there is nothing to draw anyway.
+            if size.x <= 0 || size.y <= 0 {
+                continue;
+            }
+            let view = scene.camera.size.as_ivec2();
+
+            // Repeated images start from the last copy that is left of or above the
+            // screen.
+            let mut start = layer.screen_position(&scene.camera).floor().as_ivec2();
+            let mut end = start + 1;
+            if layer.repeat_x {
+                start.x = start.x.rem_euclid(size.x) - size.x;
+                end.x = view.x;
+            }
+
+            if layer.repeat_y {
+                start.y = start.y.rem_euclid(size.y) - size.y;
+                end.y = view.y;
+            }
+
+            let [width, height] = [size.x as u32, size.y as u32];
+            for y in (start.y..end.y).step_by(size.y as usize) {
+                for x in (start.x..end.x).step_by(size.x as usize) {
+                    self.canvas
+                        .copy(&texture, None, Rect::new(x, y, width, height))
+                        .unwrap();
+                }
+            }
+        }
+    }
+
+    /// Draws the tile layers of the scene that can be seen by the camera,
+    /// either those behind or those in front of the player and enemies.
+    ///
+    /// The tiles are drawn ahead of time into chunks, which are only drawn
+    /// again when the tiles or their sprite sheets change.
+    pub fn draw_tiles(&mut self, scene: &mut Scene, foreground: bool) {
+        if self.chunks.len() > scene.layers.len() {
+            for chunks in self.chunks.drain(scene.layers.len()..).flatten() {
+                chunks.destroy();
+            }
+        }
+
+        self.chunks.resize_with(scene.layers.len(), || None);
+
+        for (i, layer) in scene.layers.iter().enumerate() {
+            if layer.foreground != foreground {
+                continue;
+            }
+
+            let stale = match &self.chunks[i] {
+                Some(chunks) => chunks.tiles != layer.tiles || !self.sheets_are_current(chunks),
+                None => true,
+            };
+
+            if stale {
+                let chunks = self.build_chunks(&layer.tiles);
+                if let Some(old) = self.chunks[i].replace(chunks) {
+                    old.destroy();
+                }
+            }
+
+            let chunks = self.chunks[i].as_ref().unwrap();
+            let sheets = chunks.sheets.iter().map(|(path, _)| path.clone());
+            self.drawn.extend(sheets);
+
+            let camera = &scene.camera;
+            let chunk_size = (TileChunks::TILES * Self::TILE_SIZE) as i32;
+            let first = (camera.position / chunk_size as f32).floor().as_ivec2();
+            let last = ((camera.position + camera.size) / chunk_size as f32)
+                .floor()
+                .as_ivec2();
+
+            for y in first.y..=last.y {
+                for x in first.x..=last.x {
+                    let Some(texture) = chunks.chunks.get(&ivec2(x, y)) else {
+                        continue;
+                    };
+
+                    let position = ivec2(x, y) * chunk_size - camera.position.floor().as_ivec2();
+                    let size = chunk_size as u32;
+                    self.canvas
+                        .copy(texture, None, Rect::new(position.x, position.y, size, size))
+                        .unwrap();
+                }
+            }
+        }
+    }
+
+    /// Returns whether the sprite sheets the chunks were drawn with are still
+    /// the newest versions.
+    fn sheets_are_current(&self, chunks: &TileChunks) -> bool {
+        chunks.sheets.iter().all(|(path, texture)| {
+            let current = self.textures.get(path).map(Handle::get);
+            current.is_some_and(|current| Rc::ptr_eq(&current, texture))
+        })
+    }
+
+    fn build_chunks(&mut self, tiles: &[MapTile]) -> TileChunks {
+        let mut sheets = HashMap::new();
+        let mut copies: HashMap<IVec2, Vec<_>> = HashMap::new();
+        for tile in tiles {
+            if !self.tile_table.is_visible(tile) {
+                continue;
+            }
+
+            let sprite = self.tile_table.sprite(&tile.block);
+            let (path, texture, frame) = self.sprite_texture(&sprite);
+            sheets.insert(path, texture.clone());
+
+            let tiles_per_chunk = TileChunks::TILES as i32;
+            let chunk = (tile.coordinate.as_vec2() / tiles_per_chunk as f32)
+                .floor()
+                .as_ivec2();
+            let position = (tile.coordinate - chunk * tiles_per_chunk) * Self::TILE_SIZE as i32;
+            let [width, height] = frame.size.to_array();
+            copies.entry(chunk).or_default().push((
+                texture,
+                Rect::new(frame.position.x as _, frame.position.y as _, width, height),
+                Rect::new(position.x, position.y, width, height),
+            ));
+        }
+
+        let size = TileChunks::TILES * Self::TILE_SIZE;
+        let mut chunks = HashMap::with_capacity(copies.len());
+        for (chunk, copies) in copies {
+            let mut texture = self
+                .creator
+                .create_texture_target(PixelFormatEnum::ABGR8888, size, size)
+                .unwrap();
+            texture.set_blend_mode(BlendMode::Blend);
+
+            self.canvas
+                .with_texture_canvas(&mut texture, |canvas| {
+                    canvas.set_draw_color(Color::RGBA(0, 0, 0, 0));
+                    canvas.clear();
+                    for (texture, source, destination) in copies {
+                        canvas.copy(&texture, source, destination).unwrap();
                     }
                 })
-                .collect();
-
-            let mut texture = creator
-                .create_texture_static(PixelFormatEnum::ABGR8888, image.width(), image.height())
                 .unwrap();
 
-            let pitch = image.width() as usize * std::mem::size_of::<[u8; 4]>();
-            texture.update(None, &pixels, pitch).unwrap();
-            texture.set_blend_mode(BlendMode::Blend);
+            chunks.insert(chunk, texture);
+        }
 
-            cache.insert(asset_path, (texture, 1));
-            cache.get(asset_path).map(|(texture, _)| texture).unwrap()
+        TileChunks {
+            tiles: tiles.to_vec(),
+            sheets: sheets.into_iter().collect(),
+            chunks,
+        }
+    }
+
+    /// Returns the sprite sheet `sprite` is drawn from, along with the path of
+    /// the sheet and where the sprite is in it.
+    fn sprite_texture(&mut self, sprite: &Sprite) -> (PathBuf, Rc<Texture>, AtlasFrame) {
+        let Self {
+            assets,
+            textures,
+            drawn,
+            atlases,
+            ..
+        } = self;
+
+        let Some((atlas, frame)) = atlases.frame(&sprite.atlas, &sprite.frame) else {
+            panic!("There is no frame called {}/{}", sprite.atlas, sprite.frame);
         };
 
-        let [x, y] = bounding_box.0.as_ref();
-        let [width, height] = bounding_box.1.as_ref();
+        let image_path = &atlas.image;
+        drawn.insert(image_path.to_owned());
+        let texture = match textures.get(image_path) {
+            Some(texture) => texture,
+            None => {
+                let texture =
+                    assets
+                        .texture(image_path, &atlas.color_key)
+                        .unwrap_or_else(|error| {
+                            panic!(
+                                "The sprite sheet {} could not be loaded: {error}",
+                                image_path.display()
+                            )
+                        });
 
-        // konverter til i32
-        let mut pos_x = position.x as i32;
-        pos_x -= camera.position.x as i32;
+                textures.entry(image_path.to_owned()).or_insert(texture)
+            }
+        };
 
-        canvas
+        // The texture is looked up every time so a reloaded sprite sheet is used right
+        // away.
+        (image_path.to_owned(), texture.get(), frame)
+    }
+
+    pub fn draw_image(&mut self, camera: &Camera, sprite: &Sprite, position: Vec2, size: u32) {
+        let (_, texture, frame) = self.sprite_texture(sprite);
+
+        let [x, y] = frame.position.as_ref();
+        let [width, height] = frame.size.as_ref();
+
+        // Both are rounded down on their own, so everything in the world moves by
+        // the same number of pixels when the camera moves.
+        let screen_position = position.floor().as_ivec2() - camera.position.floor().as_ivec2();
+        let destination = Rect::new(screen_position.x, screen_position.y, *width, *height);
+
+        // Sprites that can't be seen are skipped.
+        let view = Rect::new(0, 0, camera.size.x as u32, camera.size.y as u32);
+        if !view.has_intersection(destination) {
+            return;
+        }
+
+        self.canvas
             .copy_ex(
-                texture,
+                &texture,
                 Rect::new(*x as _, *y as _, *width, *height),
-                Rect::new(pos_x as _, position.y as _, *width, *height),
+                destination,
                 0.0,
                 None,
-                *mirror,
+                sprite.mirror,
                 false,
             )
             .unwrap();
@@ -144,15 +524,59 @@ impl Renderer {
     }
 
     pub fn draw_player(&mut self, scene: &mut Scene) {
-        let position = uvec2(
-            scene.player.position.x as u32,
-            scene.player.position.y as u32,
-        );
+        let position = scene.player.position;
+
+        if let Some(sprite) = self.animations.sprite(&scene.player.animator) {
+            self.draw_image(&scene.camera, &sprite, position, 1);
+        }
+    }
+
+    /// Draws `text` with the top-left corner at `position` in screen-space.
+    ///
+    /// Every pixel in the font is drawn as a `scale` x `scale` square.
+    pub fn draw_text(&mut self, text: &str, position: IVec2, scale: u32, color: Color) {
+        font::draw_text(&mut self.canvas, text, position, scale, color);
+    }
 
-        self.draw_image(&scene.camera, &scene.player.to_sprite(), position, 1);
+    /// Dims everything that has been drawn so far, so that whatever is drawn
+    /// on top of it is easier to read.
+    pub fn draw_overlay(&mut self) {
+        self.canvas.set_draw_color(Color::RGBA(0, 0, 0, 160));
+        self.canvas.fill_rect(None).unwrap();
     }
 
-    pub fn draw_text(&mut self) {
-        todo!()
+    /// Draws the title and items of `menu` centered on the screen.
+    pub fn draw_menu(&mut self, menu: &Menu) {
+        const TITLE_SCALE: u32 = 2;
+        const ITEM_HEIGHT: i32 = 14;
+
+        let screen_width = (Self::TILES_X * Self::TILE_SIZE) as i32;
+        let screen_height = (Self::TILES_Y * Self::TILE_SIZE) as i32;
+        let centered = |width: u32| (screen_width - width as i32) / 2;
+
+        let title_width = font::text_width(&menu.title) * TITLE_SCALE;
+        let title_y = screen_height / 4;
+        self.draw_text(
+            &menu.title,
+            ivec2(centered(title_width), title_y),
+            TITLE_SCALE,
+            Color::WHITE,
+        );
+
+        let items_height = menu.items.len() as i32 * ITEM_HEIGHT;
+        let items_y = (screen_height - items_height) / 2 + ITEM_HEIGHT;
+
+        for (i, item) in menu.items.iter().enumerate() {
+            let x = centered(font::text_width(item));
+            let y = items_y + i as i32 * ITEM_HEIGHT;
+
+            if i == menu.selected {
+                let color = Color::RGB(252, 188, 176);
+                self.draw_text(">", ivec2(x - 12, y), 1, color);
+                self.draw_text(item, ivec2(x, y), 1, color);
+            } else {
+                self.draw_text(item, ivec2(x, y), 1, Color::WHITE);
+            }
+        }
     }
 }
@@ -1,36 +1,56 @@
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use ::glam::*;
 use ::sdl2::event::{Event, WindowEvent};
 use ::sdl2::keyboard::*;
 use ::sdl2::mouse::*;
 use ::sdl2::video::*;
-use ::sdl2::{AudioSubsystem, VideoSubsystem};
+use ::sdl2::{AudioSubsystem, GameControllerSubsystem, VideoSubsystem};
 use futures::executor::ThreadPool;
 
+use crate::assets::*;
 use crate::audio::AudioManager;
+use crate::audio_backend::*;
 use crate::game::*;
+use crate::input_handler::*;
 use crate::renderer::*;
 use crate::scene::*;
+use crate::screen::*;
+use crate::settings::*;
+use crate::sound::*;
 use crate::Layer;
 
 pub struct Runtime {
     video: VideoSubsystem,
-    audio: AudioSubsystem,
+    audio: Option<AudioSubsystem>,
 
     should_close: bool,
 
     renderer: Renderer,
+    input: InputHandler,
     scene: Scene,
 
     systems: GameSystems,
     game: Game,
+    settings: Settings,
+
+    screens: Vec<Box<dyn Screen>>,
+    last_update: Instant,
 }
 
 impl Runtime {
-    pub fn new(video: sdl2::VideoSubsystem, audio: sdl2::AudioSubsystem) -> Self {
+    pub fn new(
+        video: sdl2::VideoSubsystem,
+        audio: Option<sdl2::AudioSubsystem>,
+        controller: GameControllerSubsystem,
+        level: Option<String>,
+    ) -> Self {
+        let settings = Settings::load();
+
+        let [width, height] = settings.video.window_size.max(UVec2::ONE).to_array();
         let window = video
-            .window("Mario", 1200, 600)
+            .window("Mario", width, height)
+            .position_centered()
             .resizable()
             .build()
             .unwrap();
@@ -42,62 +62,189 @@ impl Runtime {
             .build()
             .unwrap();
 
-        let renderer = Renderer::new(canvas);
+        let input = InputHandler::new(controller);
 
         let mut scene = Scene {
-            camera: Camera::new(vec2(0.0, 0.0)),
+            camera: Camera::new(Renderer::SCREEN_SIZE),
             enemies: Vec::default(),
             entities: Vec::default(),
             player: Player::new(vec2(10.0, 10.0)),
             text: Vec::default(),
-            tiles: Vec::default(),
+            layers: Vec::default(),
             background: uvec3(146, 144, 255),
+            backgrounds: Vec::new(),
+            player_behind: false,
+            cover: None,
         };
 
         let systems = {
-            let audio_manager = AudioManager::new(audio.clone());
-            audio_manager.register_dir(&"./assets/audio", true);
+            let thread_pool = ThreadPool::new().unwrap();
+            let backend = match &audio {
+                Some(audio) => SdlBackend::open(audio.clone()),
+                None => Err("SDL audio isn't available".to_owned()),
+            };
+
+            let audio_manager = match backend {
+                Ok(backend) => AudioManager::new(backend, thread_pool.clone()),
+                Err(error) => {
+                    eprintln!("The game will be silent because the audio device could not be opened: {error}");
+                    AudioManager::new(NullBackend::new(), thread_pool.clone())
+                }
+            };
+            for error in audio_manager.register_dir(&"./assets/audio", true) {
+                eprintln!("Skipped a file in the audio folder: {error}");
+            }
+            if let Err(error) = Sound::check(&audio_manager) {
+                panic!("{error}");
+            }
+
+            audio_manager.set_settings(settings.audio);
+
+            let assets = AssetManager::new(canvas.texture_creator(), audio_manager.clone());
 
             GameSystems {
                 audio: audio_manager,
-                thread_pool: ThreadPool::new().unwrap(),
+                assets,
+                thread_pool,
             }
         };
 
-        let game = Game::new(&mut scene, &systems);
+        let mut renderer = Renderer::new(canvas, systems.assets.clone());
+        renderer.apply_settings(&settings.video);
+
+        let mut game = Game::new();
+
+        // Skip the title screen when a level has been given on the command line.
+        let screen: Box<dyn Screen> = match level {
+            Some(level) if game.has_level(&level) => {
+                game.load_level(&level, &mut scene, &systems);
+                Box::new(GameplayScreen::new())
+            }
+            Some(level) => {
+                let levels = game.level_names().join(", ");
+                eprintln!(
+                    "There is no level called \"{level}\". The available levels are: {levels}"
+                );
+                Box::new(TitleScreen::new())
+            }
+            None => Box::new(TitleScreen::new()),
+        };
 
         Self {
             video,
             audio,
             should_close: false,
             renderer,
+            input,
             scene,
 
             systems,
             game,
+            settings,
+
+            screens: vec![screen],
+            last_update: Instant::now(),
         }
     }
+
+    fn apply(&mut self, transition: Transition) {
+        let Self { screens, .. } = self;
+
+        match transition {
+            Transition::None => {}
+            Transition::Push(screen) => screens.push(screen),
+            Transition::Pop => {
+                screens.pop();
+            }
+            Transition::Replace(screen) => {
+                screens.pop();
+                screens.push(screen);
+            }
+            Transition::Reset(screen) => {
+                screens.clear();
+                screens.push(screen);
+            }
+            Transition::Quit => screens.clear(),
+        }
+
+        // There is nothing left to show once the last screen is gone.
+        if screens.is_empty() {
+            self.close();
+        }
+    }
+
+    fn close(&mut self) {
+        // Keep the size the window was resized to for the next time the game is
+        // started.
+        if let Err(error) = self.settings.save() {
+            eprintln!("The settings could not be saved: {error}");
+        }
+
+        self.should_close = true;
+    }
 }
 
 impl Layer for Runtime {
     fn update(&mut self, keyboard: KeyboardState, mouse: MouseState) {
+        let now = Instant::now();
+        let delta = now - self.last_update;
+        self.last_update = now;
+
+        self.input.update(&keyboard);
+
+        let video = self.settings.video;
+        if let Some(screen) = self.screens.last_mut() {
+            let mut cx = Context {
+                scene: &mut self.scene,
+                game: &mut self.game,
+                systems: &self.systems,
+                settings: &mut self.settings,
+                input: &self.input,
+                keyboard: &keyboard,
+                delta,
+            };
+
+            let transition = screen.update(&mut cx);
+            self.apply(transition);
+        }
+
+        if self.settings.video != video {
+            self.renderer.apply_settings(&self.settings.video);
+        }
+
+        self.systems.audio.update();
+        for path in self.systems.assets.update() {
+            self.game.reload(&path, &mut self.scene);
+        }
+
+        // Overlays are drawn on top of the screens below them, so we start from the
+        // top-most screen that covers everything.
         let Self {
-            game,
-            systems,
             renderer,
+            screens,
+            scene,
             ..
         } = self;
 
-        game.update(&mut self.scene, systems, keyboard);
-        systems.audio.update();
-        renderer.update(&mut self.scene);
+        let first = screens.iter().rposition(|screen| !screen.is_overlay());
+        renderer.clear();
+        for screen in &mut screens[first.unwrap_or(0)..] {
+            screen.render(renderer, scene);
+        }
+        renderer.present();
     }
 
     fn handle_events(&mut self, events: &mut dyn Iterator<Item = &sdl2::event::Event>) {
         for event in events {
             match event {
                 Event::Window { win_event, .. } if *win_event == WindowEvent::Close => {
-                    self.should_close = true;
+                    self.close();
+                }
+                Event::Window {
+                    win_event: WindowEvent::Resized(width, height),
+                    ..
+                } if !self.settings.video.fullscreen => {
+                    self.settings.video.window_size = uvec2(*width as u32, *height as u32);
                 }
                 _ => {}
             }
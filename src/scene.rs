@@ -1,10 +1,11 @@
-use std::cell::RefCell;
-use std::time::Instant;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
 
 use glam::*;
 use sdl2::pixels::*;
 use serde::{Deserialize, Serialize};
 
+use crate::animation::*;
 use crate::game::BoundingBox;
 use crate::map::*;
 
@@ -47,14 +48,82 @@ pub trait ToSprite {
     fn to_sprite(&self) -> Sprite;
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+/// The part of the world that is shown on the screen.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct Camera {
+    /// The top-left corner of the view.
     pub position: Vec2,
+    pub size: Vec2,
+    /// How far the target can move from the center of the view in each
+    /// direction before the camera starts following it.
+    pub dead_zone: Vec2,
+    /// The top-left and bottom-right corners of the part of the world the
+    /// camera never shows past.
+    pub bounds: Option<(Vec2, Vec2)>,
+    /// Whether the camera follows the target up and down.
+    pub vertical: bool,
+    /// Roughly how many seconds it takes the camera to catch up with the
+    /// target. The camera moves right away when this is zero.
+    pub smoothing: f32,
+    /// Whether the camera is allowed to scroll back to the left.
+    pub scroll_back: bool,
 }
 
 impl Camera {
-    pub fn new(position: Vec2) -> Self {
-        Self { position }
+    pub fn new(size: Vec2) -> Self {
+        Self {
+            position: Vec2::ZERO,
+            size,
+            dead_zone: vec2(8.0, 32.0),
+            bounds: None,
+            vertical: true,
+            smoothing: 0.1,
+            scroll_back: true,
+        }
+    }
+
+    pub fn center(&self) -> Vec2 {
+        self.position + self.size * 0.5
+    }
+
+    /// Moves the camera toward `target` until the target is inside the
+    /// dead-zone.
+    pub fn follow(&mut self, target: Vec2, delta: Duration) {
+        let offset = target - self.center();
+        let mut goal = self.position + offset - offset.clamp(-self.dead_zone, self.dead_zone);
+        if !self.vertical {
+            goal.y = self.position.y;
+        }
+
+        let t = if self.smoothing > 0.0 {
+            1.0 - (-delta.as_secs_f32() / self.smoothing).exp()
+        } else {
+            1.0
+        };
+
+        let mut position = self.position.lerp(goal, t);
+        if !self.scroll_back {
+            position.x = position.x.max(self.position.x);
+        }
+
+        self.position = self.clamp(position);
+    }
+
+    /// Centers the camera on `target` right away.
+    pub fn snap_to(&mut self, target: Vec2) {
+        self.position = self.clamp(target - self.size * 0.5);
+    }
+
+    fn clamp(&self, position: Vec2) -> Vec2 {
+        let Some((min, max)) = self.bounds else {
+            return position;
+        };
+
+        // Bounds that are smaller than the view are shown from their bottom-left
+        // corner, where the ground is.
+        let max = max - self.size;
+        let min = vec2(min.x, min.y.min(max.y));
+        position.clamp(min, max.max(min))
     }
 }
 
@@ -68,9 +137,34 @@ pub enum Direction {
 pub struct Enemy {
     pub position: Vec2,
     pub kind: EnemyKind,
+    #[serde(skip)]
+    pub animator: Animator,
 }
 
 impl Enemy {
+    /// Chooses the animation that fits what the enemy is doing and advances it
+    /// by `delta`.
+    pub fn animate(&mut self, delta: Duration) {
+        let animator = &mut self.animator;
+        match &self.kind {
+            EnemyKind::Goomba { direction, .. } => {
+                animator.play("goomba_walk");
+                animator.mirror = *direction == Direction::Forward;
+            }
+            EnemyKind::Piranha {} => animator.play("piranha_bite"),
+            EnemyKind::Koopa { shell: Some(_), .. } => {
+                animator.play("koopa_shell");
+                animator.mirror = false;
+            }
+            EnemyKind::Koopa { direction, .. } => {
+                animator.play("koopa_walk");
+                animator.mirror = *direction == Direction::Backward;
+            }
+        }
+
+        animator.advance(delta);
+    }
+
     pub fn is_goomba(&self) -> bool {
         matches!(self.kind, EnemyKind::Goomba { .. })
     }
@@ -84,7 +178,7 @@ impl Enemy {
     }
 
     pub fn collider(&self) -> BoundingBox {
-        let Self { position, kind } = self;
+        let Self { position, kind, .. } = self;
         let [x, y] = position.as_ref();
         let (width, height) = match kind {
             EnemyKind::Goomba { .. } => (16.0, 16.0),
@@ -107,19 +201,13 @@ pub enum EnemyKind {
         from: Vec2,
         to: Vec2,
         direction: Direction,
-        #[serde(skip)]
-        frame: RefCell<u32>,
-    },
-    Piranha {
-        #[serde(skip)]
-        frame: RefCell<u32>,
     },
+    // This stays a struct variant so that existing levels can still be read.
+    Piranha {},
     Koopa {
         direction: Direction,
         #[serde(skip)]
         shell: Option<Instant>,
-        #[serde(skip)]
-        frame: RefCell<u32>,
     },
 }
 
@@ -129,9 +217,9 @@ pub enum Item {
     Flower,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
 pub struct Entity {
-    pub position: UVec2,
+    pub position: Vec2,
     pub kind: EntityKind,
 }
 
@@ -141,6 +229,7 @@ impl Entity {
 
         let (width, height) = match kind {
             EntityKind::Pipe { id: _ } => (33.0, 34.0),
+            EntityKind::Flag => (16.0, 168.0),
             _ => unimplemented!(),
         };
 
@@ -158,6 +247,7 @@ pub enum EntityKind {
     Coin,
     Pipe { id: usize },
     Item(Item),
+    Flag,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -171,7 +261,8 @@ pub struct Player {
     pub jump_velocity: f32,
     pub fall_velocity: f32,
     pub can_jump: bool,
-    frame: RefCell<u32>,
+    #[serde(skip)]
+    pub animator: Animator,
 }
 
 impl Player {
@@ -183,65 +274,24 @@ impl Player {
             jump_velocity: 0.0,
             fall_velocity: 0.0,
             can_jump: true,
-            frame: RefCell::new(0),
+            animator: Animator::new("mario_idle"),
         }
     }
-}
 
-impl ToSprite for Player {
-    fn to_sprite(&self) -> Sprite {
-        let mut frame = self.frame.borrow_mut();
-
-        let mut sprite = if self.fall_velocity != 0.0 {
-            *frame = 0;
-            Sprite::new(
-                (uvec2(96, 8), uvec2(16, 16)),
-                "assets/sprites/characters.png",
-                false,
-            )
+    /// Chooses the animation that fits what the player is doing and advances
+    /// it by `delta`.
+    pub fn animate(&mut self, delta: Duration) {
+        let clip = if self.fall_velocity != 0.0 {
+            "mario_jump"
         } else if self.move_velocity != 0.0 {
-            match *frame {
-                0 | 1 | 2 => {
-                    *frame += 1;
-                    Sprite::new(
-                        (uvec2(56, 8), uvec2(16, 16)),
-                        "assets/sprites/characters.png",
-                        false,
-                    )
-                }
-                3 | 4 | 5 | 6 | 7 | 8 => {
-                    *frame += 1;
-                    Sprite::new(
-                        (uvec2(20, 8), uvec2(16, 16)),
-                        "assets/sprites/characters.png",
-                        false,
-                    )
-                }
-                9 | 10 | 11 | 12 | 13 | 14 => {
-                    *frame += 1;
-                    if *frame == 15 {
-                        *frame = 0
-                    };
-
-                    Sprite::new(
-                        (uvec2(38, 8), uvec2(16, 16)),
-                        "assets/sprites/characters.png",
-                        false,
-                    )
-                }
-                _ => unreachable!(),
-            }
+            "mario_run"
         } else {
-            *frame = 0;
-            Sprite::new(
-                (uvec2(0, 8), uvec2(16, 16)),
-                "assets/sprites/characters.png",
-                false,
-            )
+            "mario_idle"
         };
 
-        sprite.mirror = self.direction == Direction::Backward;
-        sprite
+        self.animator.play(clip);
+        self.animator.mirror = self.direction == Direction::Backward;
+        self.animator.advance(delta);
     }
 }
 
@@ -262,8 +312,78 @@ pub struct Scene {
 
     pub text: Vec<Text>,
 
-    pub tiles: Vec<MapTile>,
+    pub layers: Vec<TileLayer>,
     pub background: UVec3,
+    pub backgrounds: Vec<BackgroundLayer>,
+
+    /// Whether the player is drawn behind the entities, such as while going
+    /// through a pipe.
+    pub player_behind: bool,
+    /// What is drawn over the whole scene, if anything.
+    pub cover: Option<ScreenCover>,
+}
+
+impl Scene {
+    /// Returns the tiles the player and enemies collide with from every side.
+    pub fn solid_tiles(&self, table: &TileTable) -> Vec<MapTile> {
+        self.collision_tiles(table, |collision| collision == Collision::Solid)
+    }
+
+    /// Returns the tiles the player and enemies can stand on.
+    pub fn ground_tiles(&self, table: &TileTable) -> Vec<MapTile> {
+        self.collision_tiles(table, |collision| collision != Collision::None)
+    }
+
+    fn collision_tiles(
+        &self,
+        table: &TileTable,
+        filter: impl Fn(Collision) -> bool,
+    ) -> Vec<MapTile> {
+        let layers = self.layers.iter().filter(|layer| layer.collision);
+        let tiles = layers.flat_map(|layer| &layer.tiles);
+        tiles
+            .filter(|tile| filter(table.get(&tile.block).collision) && table.is_visible(tile))
+            .copied()
+            .collect()
+    }
+}
+
+/// An image that is drawn behind the tiles, such as hills or clouds.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct BackgroundLayer {
+    pub image: PathBuf,
+    /// How fast the layer scrolls compared to the camera. Layers with a factor
+    /// of 0 stay put on the screen and layers with a factor of 1 move along
+    /// with the tiles.
+    pub parallax: Vec2,
+    /// Where the top-left corner of the image is when the camera is at the
+    /// origin.
+    #[serde(default)]
+    pub offset: Vec2,
+    /// Whether the image is repeated to fill the screen horizontally.
+    #[serde(default)]
+    pub repeat_x: bool,
+    #[serde(default)]
+    pub repeat_y: bool,
+}
+
+/// Something that is drawn over the whole scene, such as during transitions.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub enum ScreenCover {
+    /// Black that is fully transparent at 0 and opaque at 1.
+    Fade(f32),
+    /// Black everywhere except for a circle of `radius` around `center`, which
+    /// is in world-space.
+    Iris { center: Vec2, radius: f32 },
+    /// Black with the name of the level in the middle.
+    TitleCard(String),
+}
+
+impl BackgroundLayer {
+    /// Returns where the top-left corner of the image is on the screen.
+    pub fn screen_position(&self, camera: &Camera) -> Vec2 {
+        self.offset - camera.position * self.parallax
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -274,138 +394,75 @@ pub struct SpriteId(usize);
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Sprite {
-    pub bounding_box: (UVec2, UVec2),
-    pub asset_path: &'static str,
+    /// The name of the atlas the frame is in.
+    pub atlas: String,
+    pub frame: String,
     pub mirror: bool,
 }
 
 impl Sprite {
-    /// Creates a new sprite from a bounding box.
-    ///
-    /// The coordinates in the second UVec2 is relative to the top-left of the
-    /// bounding box.
-    pub fn new(bounding_box: (UVec2, UVec2), asset_path: &'static str, mirror: bool) -> Sprite {
+    /// Creates a sprite that shows the frame named `frame` in `atlas`.
+    pub fn new(atlas: impl Into<String>, frame: impl Into<String>, mirror: bool) -> Sprite {
         Sprite {
-            bounding_box,
-            asset_path,
+            atlas: atlas.into(),
+            frame: frame.into(),
             mirror,
         }
     }
 }
 
-impl ToSprite for Enemy {
+impl ToSprite for Entity {
     fn to_sprite(&self) -> Sprite {
-        match &self.kind {
-            EnemyKind::Goomba {
-                frame, direction, ..
-            } => {
-                let mut frame = frame.borrow_mut();
-                match *frame {
-                    0..=15 => {
-                        *frame += 1;
-                        Sprite::new(
-                            (uvec2(0, 16), uvec2(16, 16)),
-                            "./assets/sprites/enemies.png",
-                            direction == &Direction::Forward,
-                        )
-                    }
-                    16..=32 => {
-                        *frame += 1;
-                        if *frame == 25 {
-                            *frame = 0
-                        }
-
-                        Sprite::new(
-                            (uvec2(18, 16), uvec2(16, 16)),
-                            "./assets/sprites/enemies.png",
-                            direction == &Direction::Forward,
-                        )
-                    }
-                    _ => unreachable!(),
-                }
-            }
-            EnemyKind::Piranha { frame } => {
-                let mut frame = frame.borrow_mut();
-                match *frame {
-                    0..=35 => {
-                        *frame += 1;
-                        Sprite::new(
-                            (uvec2(0, 138), uvec2(16, 24)),
-                            "./assets/sprites/enemies.png",
-                            false,
-                        )
-                    }
-                    36..=72 => {
-                        *frame += 1;
-                        if *frame == 72 {
-                            *frame = 0;
-                        }
-
-                        Sprite::new(
-                            (uvec2(18, 138), uvec2(16, 24)),
-                            "./assets/sprites/enemies.png",
-                            false,
-                        )
-                    }
-                    _ => unreachable!(),
-                }
-            }
-            EnemyKind::Koopa {
-                frame,
-                direction,
-                shell,
-            } => {
-                let mut frame = frame.borrow_mut();
-                if shell.is_some() {
-                    *frame = 0;
-                    return Sprite::new(
-                        (uvec2(72, 120), uvec2(16, 16)),
-                        "./assets/sprites/enemies.png",
-                        false,
-                    );
-                }
-
-                let mut sprite = match *frame {
-                    0..=35 => {
-                        *frame += 1;
-                        Sprite::new(
-                            (uvec2(0, 112), uvec2(16, 24)),
-                            "./assets/sprites/enemies.png",
-                            false,
-                        )
-                    }
-                    36..=72 => {
-                        *frame += 1;
-                        if *frame == 73 {
-                            *frame = 0;
-                        }
-                        Sprite::new(
-                            (uvec2(18, 112), uvec2(16, 24)),
-                            "./assets/sprites/enemies.png",
-                            false,
-                        )
-                    }
-                    _ => unreachable!(),
-                };
-
-                sprite.mirror = direction == &Direction::Backward;
-                sprite
-            }
-            _ => unreachable!(),
+        match self.kind {
+            EntityKind::Coin => Sprite::new("tilesheet", "coin", false),
+            EntityKind::Pipe { .. } => Sprite::new("tilesheet", "pipe", false),
+            // There are no sprites for the items yet, so they are shown as the
+            // block they come out of.
+            EntityKind::Item(..) => Sprite::new("tilesheet", "question", false),
+            EntityKind::Flag => Sprite::new("tilesheet", "flag", false),
         }
     }
 }
 
-impl ToSprite for Entity {
-    fn to_sprite(&self) -> Sprite {
-        match self.kind {
-            EntityKind::Coin => Sprite::new((uvec2(0, 10), uvec2(0, 10)), "", false),
-            EntityKind::Pipe { .. } => Sprite::new(
-                (uvec2(119, 196), uvec2(33, 34)),
-                "./assets/sprites/tilesheet.png",
-                false,
-            ),
-            EntityKind::Item(..) => Sprite::new((uvec2(0, 10), uvec2(0, 10)), "", false),
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_camera_follow() {
+        let mut camera = Camera::new(vec2(100.0, 100.0));
+        camera.dead_zone = vec2(10.0, 10.0);
+        camera.smoothing = 0.0;
+        camera.bounds = Some((Vec2::ZERO, vec2(300.0, 100.0)));
+
+        // The camera doesn't move while the target is inside the dead-zone.
+        camera.follow(vec2(55.0, 50.0), Duration::ZERO);
+        assert_eq!(camera.position, Vec2::ZERO);
+
+        camera.follow(vec2(100.0, 80.0), Duration::ZERO);
+        assert_eq!(camera.position, vec2(40.0, 0.0));
+
+        camera.follow(vec2(1000.0, 50.0), Duration::ZERO);
+        assert_eq!(camera.position, vec2(200.0, 0.0));
+
+        camera.scroll_back = false;
+        camera.follow(vec2(0.0, 50.0), Duration::ZERO);
+        assert_eq!(camera.position, vec2(200.0, 0.0));
+    }
+
+    #[test]
+    fn test_background_layer_parallax() {
+        let mut camera = Camera::new(vec2(100.0, 100.0));
+        camera.position = vec2(200.0, 40.0);
+
+        let layer = BackgroundLayer {
+            image: PathBuf::new(),
+            parallax: vec2(0.5, 0.0),
+            offset: vec2(0.0, 20.0),
+            repeat_x: true,
+            repeat_y: false,
+        };
+
+        assert_eq!(layer.screen_position(&camera), vec2(-100.0, 20.0));
     }
 }
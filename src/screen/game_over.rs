@@ -0,0 +1,35 @@
+use super::*;
+use crate::menu::*;
+
+pub struct GameOverScreen {
+    menu: Menu,
+}
+
+impl GameOverScreen {
+    pub fn new() -> Self {
+        let items = ["Retry", "Quit"];
+        Self {
+            menu: Menu::new("Game Over", items.map(String::from).to_vec()),
+        }
+    }
+}
+
+impl Screen for GameOverScreen {
+    fn update(&mut self, cx: &mut Context) -> Transition {
+        match self.menu.handle_input(cx.input) {
+            Some(0) => {
+                cx.game.restart(cx.scene, cx.systems);
+                Transition::Replace(Box::new(GameplayScreen::new()))
+            }
+            Some(_) => Transition::Replace(Box::new(TitleScreen::new())),
+            None => Transition::None,
+        }
+    }
+
+    fn render(&mut self, renderer: &mut Renderer, scene: &mut Scene) {
+        // The level is kept in the background so the player can see where they died.
+        renderer.draw_scene(scene);
+        renderer.draw_overlay();
+        renderer.draw_menu(&self.menu);
+    }
+}
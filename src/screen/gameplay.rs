@@ -0,0 +1,53 @@
+use ::glam::*;
+use ::sdl2::pixels::Color;
+
+use super::*;
+use crate::audio::*;
+
+/// The screen where the current level is played.
+pub struct GameplayScreen {
+    /// The number of seconds left on the timer, shown in the corner of the
+    /// screen.
+    time: Option<u64>,
+}
+
+impl GameplayScreen {
+    pub fn new() -> Self {
+        Self { time: None }
+    }
+}
+
+impl Screen for GameplayScreen {
+    fn update(&mut self, cx: &mut Context) -> Transition {
+        if cx.input.is_pressed(Action::Pause) && !cx.game.is_dead() {
+            cx.systems.audio.pause_music();
+            cx.systems.audio.start(&Sound::Pause);
+            return Transition::Push(Box::new(PauseScreen::new()));
+        }
+
+        let outcome = cx.game.update(cx.scene, cx.systems, cx.keyboard, cx.delta);
+        self.time = cx.game.time_left().map(|time| time.as_secs());
+
+        match outcome {
+            Outcome::Playing => Transition::None,
+            Outcome::Completed => Transition::Replace(Box::new(LevelSelectScreen::new(cx.game))),
+            Outcome::GameOver => {
+                cx.systems
+                    .audio
+                    .start_with(&Sound::GameOver, PlayOptions::with_priority(Priority::High));
+                Transition::Replace(Box::new(GameOverScreen::new()))
+            }
+        }
+    }
+
+    fn render(&mut self, renderer: &mut Renderer, scene: &mut Scene) {
+        renderer.draw_scene(scene);
+
+        if let Some(time) = self.time {
+            let text = format!("Time {time:03}");
+            let x = (Renderer::TILES_X * Renderer::TILE_SIZE) as i32 - 8;
+            let width = crate::font::text_width(&text) as i32;
+            renderer.draw_text(&text, ivec2(x - width, 8), 1, Color::WHITE);
+        }
+    }
+}
@@ -0,0 +1,54 @@
+use ::glam::*;
+
+use super::*;
+use crate::menu::*;
+
+pub struct LevelSelectScreen {
+    menu: Menu,
+    levels: Vec<String>,
+}
+
+impl LevelSelectScreen {
+    pub fn new(game: &Game) -> Self {
+        let levels = game.level_names().to_vec();
+
+        let mut items: Vec<_> = levels
+            .iter()
+            .map(|name| {
+                if game.is_completed(name) {
+                    format!("{name} - Clear")
+                } else {
+                    name.clone()
+                }
+            })
+            .collect();
+
+        items.push("Back".to_owned());
+
+        Self {
+            menu: Menu::new("Select Level", items),
+            levels,
+        }
+    }
+}
+
+impl Screen for LevelSelectScreen {
+    fn update(&mut self, cx: &mut Context) -> Transition {
+        match self.menu.handle_input(cx.input) {
+            Some(i) if i < self.levels.len() => {
+                cx.game.load_level(&self.levels[i], cx.scene, cx.systems);
+                Transition::Replace(Box::new(GameplayScreen::new()))
+            }
+            Some(_) => Transition::Replace(Box::new(TitleScreen::new())),
+            None if cx.input.is_pressed(Action::Back) => {
+                Transition::Replace(Box::new(TitleScreen::new()))
+            }
+            None => Transition::None,
+        }
+    }
+
+    fn render(&mut self, renderer: &mut Renderer, _scene: &mut Scene) {
+        renderer.draw_background(Rgba::from(vec3(146.0, 144.0, 255.0) / 255.0));
+        renderer.draw_menu(&self.menu);
+    }
+}
@@ -0,0 +1,79 @@
+//! The screens the game is made out of, such as the title screen, the level
+//! itself and the pause menu.
+//!
+//! The runtime keeps a stack of screens where only the top-most screen is
+//! updated. Screens can push new screens on top of themselves, pop themselves
+//! off the stack or replace themselves with another screen.
+
+use std::time::Duration;
+
+use ::sdl2::keyboard::KeyboardState;
+
+use crate::game::*;
+use crate::input_handler::*;
+use crate::renderer::*;
+use crate::scene::*;
+use crate::settings::*;
+use crate::sound::*;
+
+mod game_over;
+mod gameplay;
+mod level_select;
+mod options;
+mod pause;
+mod title;
+
+pub use self::game_over::*;
+pub use self::gameplay::*;
+pub use self::level_select::*;
+pub use self::options::*;
+pub use self::pause::*;
+pub use self::title::*;
+
+/// Everything a screen has access to while it's being updated.
+///
+/// The scene is shared between all screens. Screens that need a scene of their
+/// own are free to keep one themselves.
+pub struct Context<'a> {
+    pub scene: &'a mut Scene,
+    pub game: &'a mut Game,
+    pub systems: &'a GameSystems,
+    pub settings: &'a mut Settings,
+    pub input: &'a InputHandler,
+    pub keyboard: &'a KeyboardState<'a>,
+    /// The time that has passed since the last update.
+    pub delta: Duration,
+}
+
+/// Describes how the screen stack should change after a screen has been
+/// updated.
+pub enum Transition {
+    /// Keep the stack as it is.
+    None,
+    /// Push a new screen on top of the current one.
+    Push(Box<dyn Screen>),
+    /// Remove the current screen, returning to the one below it.
+    Pop,
+    /// Replace the current screen with a new one.
+    Replace(Box<dyn Screen>),
+    /// Remove all screens and start over with a new one.
+    Reset(Box<dyn Screen>),
+    /// Close the game.
+    Quit,
+}
+
+pub trait Screen {
+    /// Updates the screen. This function is only called when the screen is at
+    /// the top of the stack.
+    fn update(&mut self, cx: &mut Context) -> Transition;
+
+    fn render(&mut self, renderer: &mut Renderer, scene: &mut Scene);
+
+    /// Returns whether the screen only covers part of the screens below it.
+    ///
+    /// The screens below an overlay are rendered before it, but they are not
+    /// updated.
+    fn is_overlay(&self) -> bool {
+        false
+    }
+}
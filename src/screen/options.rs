@@ -0,0 +1,148 @@
+use ::glam::*;
+use ::sdl2::pixels::Color;
+
+use super::*;
+use crate::audio::*;
+use crate::font;
+use crate::menu::*;
+
+/// Lets the player change the volume of the game and how it's shown on the
+/// screen.
+///
+/// The settings are applied right away and saved when the player leaves the
+/// screen.
+pub struct OptionsScreen {
+    menu: Menu,
+}
+
+impl OptionsScreen {
+    /// How much the volume changes every time left or right is pressed.
+    const STEP: f32 = 0.1;
+    /// The largest window that can be picked, as a multiple of the screen size.
+    const MAX_SCALE: u32 = 6;
+
+    pub fn new(settings: &Settings) -> Self {
+        let mut screen = Self {
+            menu: Menu::new("Options", Vec::new()),
+        };
+
+        screen.update_items(settings);
+        screen
+    }
+
+    fn update_items(&mut self, settings: &Settings) {
+        let Settings { audio, video } = settings;
+        let volume = |name: &str, volume: &Volume| {
+            if volume.muted {
+                format!("{name} Muted")
+            } else {
+                format!("{name} {:.0}%", volume.level * 100.0)
+            }
+        };
+
+        let toggle = |name: &str, on: bool| format!("{name} {}", if on { "On" } else { "Off" });
+
+        self.menu.items = vec![
+            volume("Master", &audio.master),
+            volume("Music", &audio.music),
+            volume("Sfx", &audio.sfx),
+            format!("Window {}x{}", video.window_size.x, video.window_size.y),
+            toggle("Fullscreen", video.fullscreen),
+            toggle("Integer Scaling", video.integer_scaling),
+            "Back".to_owned(),
+        ];
+    }
+
+    fn close(cx: &mut Context) -> Transition {
+        if let Err(error) = cx.settings.save() {
+            eprintln!("The settings could not be saved: {error}");
+        }
+
+        Transition::Pop
+    }
+}
+
+impl Screen for OptionsScreen {
+    fn update(&mut self, cx: &mut Context) -> Transition {
+        let confirmed = self.menu.handle_input(cx.input);
+
+        let left = cx.input.is_pressed(Action::Left);
+        let right = cx.input.is_pressed(Action::Right);
+        let changed = confirmed.is_some() || left || right;
+
+        let mut settings = cx.settings.clone();
+        let Settings { audio, video } = &mut settings;
+        match self.menu.selected {
+            selected @ 0..=2 => {
+                let volume = match selected {
+                    0 => &mut audio.master,
+                    1 => &mut audio.music,
+                    _ => &mut audio.sfx,
+                };
+
+                // The level is kept at whole steps so it can always get back to exactly 0
+                // or 1.
+                let steps = (volume.level / Self::STEP).round();
+                if left {
+                    volume.level = ((steps - 1.0) * Self::STEP).max(0.0);
+                }
+
+                if right {
+                    volume.level = ((steps + 1.0) * Self::STEP).min(1.0);
+                }
+
+                if confirmed.is_some() {
+                    volume.muted = !volume.muted;
+                }
+            }
+            3 => {
+                // Windows are picked from multiples of the screen size, which fit integer
+                // scaling exactly.
+                let screen = Renderer::SCREEN_SIZE;
+                let scale = (video.window_size.as_vec2() / screen).min_element().round() as u32;
+                let scale = if left {
+                    scale.saturating_sub(1)
+                } else if right || confirmed.is_some() {
+                    scale + 1
+                } else {
+                    scale
+                };
+
+                video.window_size = screen.as_uvec2() * scale.clamp(1, Self::MAX_SCALE);
+            }
+            4 if changed => video.fullscreen = !video.fullscreen,
+            5 if changed => video.integer_scaling = !video.integer_scaling,
+            4 | 5 => {}
+            _ if confirmed.is_some() || cx.input.is_pressed(Action::Back) => {
+                return Self::close(cx);
+            }
+            _ => return Transition::None,
+        }
+
+        if settings.audio != cx.settings.audio {
+            cx.systems.audio.set_settings(settings.audio);
+        }
+
+        // The runtime applies the video settings once the screen has been updated.
+        if settings != *cx.settings {
+            *cx.settings = settings;
+            self.update_items(cx.settings);
+        }
+
+        if cx.input.is_pressed(Action::Back) {
+            return Self::close(cx);
+        }
+
+        Transition::None
+    }
+
+    fn render(&mut self, renderer: &mut Renderer, _scene: &mut Scene) {
+        renderer.draw_background(Rgba::from(vec3(146.0, 144.0, 255.0) / 255.0));
+        renderer.draw_menu(&self.menu);
+
+        let hint = "Left/Right: Change  Enter: Toggle";
+        let x = ((Renderer::TILES_X * Renderer::TILE_SIZE - font::text_width(hint)) / 2) as i32;
+        let y = (Renderer::TILES_Y * Renderer::TILE_SIZE - font::GLYPH_HEIGHT * 3) as i32;
+        renderer.draw_text(hint, ivec2(x, y), 1, Color::WHITE);
+    }
+}
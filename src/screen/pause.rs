@@ -0,0 +1,57 @@
+use std::time::Duration;
+
+use super::*;
+use crate::menu::*;
+
+pub struct PauseScreen {
+    menu: Menu,
+}
+
+impl PauseScreen {
+    pub fn new() -> Self {
+        let items = ["Resume", "Restart", "Options", "Quit"];
+        Self {
+            menu: Menu::new("Paused", items.map(String::from).to_vec()),
+        }
+    }
+}
+
+impl Screen for PauseScreen {
+    fn update(&mut self, cx: &mut Context) -> Transition {
+        let resume = cx.input.is_pressed(Action::Pause) || cx.input.is_pressed(Action::Back);
+
+        match self.menu.handle_input(cx.input) {
+            Some(0) => {
+                cx.systems.audio.start(&Sound::Pause);
+                cx.systems.audio.resume_music();
+                Transition::Pop
+            }
+            Some(1) => {
+                // The level starts its music over when it's loaded again.
+                cx.systems.audio.stop_music(Duration::ZERO);
+                cx.game.restart(cx.scene, cx.systems);
+                Transition::Pop
+            }
+            Some(2) => Transition::Push(Box::new(OptionsScreen::new(cx.settings))),
+            Some(_) => {
+                cx.systems.audio.stop_music(Duration::ZERO);
+                Transition::Reset(Box::new(TitleScreen::new()))
+            }
+            None if resume => {
+                cx.systems.audio.start(&Sound::Pause);
+                cx.systems.audio.resume_music();
+                Transition::Pop
+            }
+            None => Transition::None,
+        }
+    }
+
+    fn render(&mut self, renderer: &mut Renderer, _scene: &mut Scene) {
+        renderer.draw_overlay();
+        renderer.draw_menu(&self.menu);
+    }
+
+    fn is_overlay(&self) -> bool {
+        true
+    }
+}
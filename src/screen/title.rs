@@ -0,0 +1,41 @@
+use ::glam::*;
+
+use super::*;
+use crate::menu::*;
+
+pub struct TitleScreen {
+    menu: Menu,
+}
+
+impl TitleScreen {
+    pub fn new() -> Self {
+        let items = ["Start", "Select Level", "Options", "Quit"];
+        Self {
+            menu: Menu::new("Super Mario Bros", items.map(String::from).to_vec()),
+        }
+    }
+}
+
+impl Screen for TitleScreen {
+    fn update(&mut self, cx: &mut Context) -> Transition {
+        match self.menu.handle_input(cx.input) {
+            // Continue from the first level that hasn't been completed yet.
+            Some(0) => match cx.game.next_level().map(str::to_owned) {
+                Some(name) => {
+                    cx.game.load_level(&name, cx.scene, cx.systems);
+                    Transition::Replace(Box::new(GameplayScreen::new()))
+                }
+                None => Transition::None,
+            },
+            Some(1) => Transition::Replace(Box::new(LevelSelectScreen::new(cx.game))),
+            Some(2) => Transition::Push(Box::new(OptionsScreen::new(cx.settings))),
+            Some(3) => Transition::Quit,
+            _ => Transition::None,
+        }
+    }
+
+    fn render(&mut self, renderer: &mut Renderer, _scene: &mut Scene) {
+        renderer.draw_background(Rgba::from(vec3(146.0, 144.0, 255.0) / 255.0));
+        renderer.draw_menu(&self.menu);
+    }
+}
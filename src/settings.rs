@@ -0,0 +1,41 @@
+use std::fs::*;
+use std::io;
+
+use ::serde::{Deserialize, Serialize};
+use ::serde_json as json;
+
+use crate::audio::*;
+use crate::renderer::VideoSettings;
+
+/// The preferences of the player, which are kept between runs of the game.
+#[derive(Debug, Clone, PartialEq, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Settings {
+    pub audio: AudioSettings,
+    pub video: VideoSettings,
+}
+
+impl Settings {
+    /// The settings are stored next to the save file.
+    const PATH: &str = "./assets/settings.json";
+
+    /// Reads the settings from disk.
+    ///
+    /// The default settings are used if the file doesn't exist or can't be
+    /// read.
+    pub fn load() -> Self {
+        let Ok(file) = File::open(Self::PATH) else {
+            return Self::default();
+        };
+
+        json::from_reader(file).unwrap_or_else(|error| {
+            eprintln!("The settings in {} are invalid: {error}", Self::PATH);
+            Self::default()
+        })
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let contents = json::to_string_pretty(self)?;
+        write(Self::PATH, contents)
+    }
+}
@@ -0,0 +1,149 @@
+use std::fmt;
+use std::path::Path;
+
+use crate::audio::*;
+
+/// Every sound effect and jingle the game plays.
+///
+/// Sounds can be given to the audio manager directly in place of a path, so a
+/// misspelled sound is caught by the compiler instead of when it's played.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Sound {
+    BowserFalls,
+    BowserFire,
+    BreakBlock,
+    Bump,
+    Coin,
+    Fireball,
+    Fireworks,
+    Flagpole,
+    JumpSmall,
+    JumpSuper,
+    Kick,
+    OneUp,
+    Pause,
+    Pipe,
+    Powerup,
+    PowerupAppears,
+    Stomp,
+    Vine,
+
+    GameOver,
+    MarioDie,
+    StageClear,
+    Warning,
+    WorldClear,
+}
+
+impl Sound {
+    pub const ALL: [Self; 23] = [
+        Self::BowserFalls,
+        Self::BowserFire,
+        Self::BreakBlock,
+        Self::Bump,
+        Self::Coin,
+        Self::Fireball,
+        Self::Fireworks,
+        Self::Flagpole,
+        Self::JumpSmall,
+        Self::JumpSuper,
+        Self::Kick,
+        Self::OneUp,
+        Self::Pause,
+        Self::Pipe,
+        Self::Powerup,
+        Self::PowerupAppears,
+        Self::Stomp,
+        Self::Vine,
+        Self::GameOver,
+        Self::MarioDie,
+        Self::StageClear,
+        Self::Warning,
+        Self::WorldClear,
+    ];
+
+    pub fn path(self) -> &'static Path {
+        let path = match self {
+            Self::BowserFalls => "./assets/audio/sfx/bowserfalls.wav",
+            Self::BowserFire => "./assets/audio/sfx/bowserfire.wav",
+            Self::BreakBlock => "./assets/audio/sfx/breakblock.wav",
+            Self::Bump => "./assets/audio/sfx/bump.wav",
+            Self::Coin => "./assets/audio/sfx/coin.wav",
+            Self::Fireball => "./assets/audio/sfx/fireball.wav",
+            Self::Fireworks => "./assets/audio/sfx/fireworks.wav",
+            Self::Flagpole => "./assets/audio/sfx/flagpole.wav",
+            Self::JumpSmall => "./assets/audio/sfx/jump_small.wav",
+            Self::JumpSuper => "./assets/audio/sfx/jump_super.wav",
+            Self::Kick => "./assets/audio/sfx/kick.wav",
+            Self::OneUp => "./assets/audio/sfx/up.wav",
+            Self::Pause => "./assets/audio/sfx/pause.wav",
+            Self::Pipe => "./assets/audio/sfx/pipe.wav",
+            Self::Powerup => "./assets/audio/sfx/powerup.wav",
+            Self::PowerupAppears => "./assets/audio/sfx/powerup_appears.wav",
+            Self::Stomp => "./assets/audio/sfx/stomp.wav",
+            Self::Vine => "./assets/audio/sfx/vine.wav",
+            Self::GameOver => "./assets/audio/clips/gameover.wav",
+            Self::MarioDie => "./assets/audio/clips/mariodie.wav",
+            Self::StageClear => "./assets/audio/clips/stage_clear.wav",
+            Self::Warning => "./assets/audio/clips/warning.wav",
+            Self::WorldClear => "./assets/audio/clips/world_clear.wav",
+        };
+
+        Path::new(path)
+    }
+
+    /// Makes sure that every sound has been registered with the audio manager.
+    pub fn check(audio: &AudioManager) -> Result<(), MissingSounds> {
+        let missing: Vec<_> = Self::ALL
+            .into_iter()
+            .filter(|sound| !audio.registered(&sound.path()))
+            .collect();
+
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(MissingSounds(missing))
+        }
+    }
+}
+
+impl AsRef<Path> for Sound {
+    fn as_ref(&self) -> &Path {
+        self.path()
+    }
+}
+
+/// The sounds that weren't found when the audio was registered.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MissingSounds(pub Vec<Sound>);
+
+impl fmt::Display for MissingSounds {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "The following sounds are missing or in an unsupported format:"
+        )?;
+        for sound in &self.0 {
+            writeln!(f, "    {sound:?}: {}", sound.path().display())?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for MissingSounds {}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::ThreadPool;
+
+    use super::*;
+    use crate::audio_backend::*;
+
+    #[test]
+    fn test_every_sound_exists() {
+        let audio = AudioManager::new(RecordingBackend::new(), ThreadPool::new().unwrap());
+        audio.register_dir(&"./assets/audio", true);
+        assert_eq!(Sound::check(&audio), Ok(()));
+    }
+}
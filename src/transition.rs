@@ -0,0 +1,164 @@
+//! The short sequences that play in between gameplay, such as the title card
+//! before a level starts and the player going down a pipe into another segment.
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use ::glam::*;
+use ::serde::{Deserialize, Serialize};
+
+use crate::scene::*;
+
+/// How the screen changes when the player enters a segment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+pub enum TransitionStyle {
+    /// The screen fades to black and back.
+    #[default]
+    Fade,
+    /// A circle around the player closes and opens up again.
+    Iris,
+    /// The segment is switched to at once.
+    Cut,
+}
+
+/// A single part of a transition.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Step {
+    /// Shows the name of the level on a black screen.
+    TitleCard(String),
+    /// Sinks the player `depth` down into a pipe, starting at `from`.
+    EnterPipe { from: Vec2, depth: f32 },
+    /// Raises the player up out of a pipe, ending at `to`.
+    ExitPipe { to: Vec2, depth: f32 },
+    /// Covers up the screen.
+    CoverOut(TransitionStyle),
+    /// Uncovers the screen again.
+    CoverIn(TransitionStyle),
+    /// Switches to another segment of the level.
+    LoadSegment(usize),
+}
+
+impl Step {
+    pub fn duration(&self) -> Duration {
+        match self {
+            Step::TitleCard(_) => Duration::from_millis(2500),
+            Step::EnterPipe { .. } | Step::ExitPipe { .. } => Duration::from_millis(800),
+            Step::CoverOut(TransitionStyle::Cut) | Step::CoverIn(TransitionStyle::Cut) => {
+                Duration::ZERO
+            }
+            Step::CoverOut(_) | Step::CoverIn(_) => Duration::from_millis(400),
+            Step::LoadSegment(_) => Duration::ZERO,
+        }
+    }
+
+    /// Returns what covers the screen when the step is `progress` of the way
+    /// through, from 0 to 1.
+    ///
+    /// Iris transitions close in on `focus`, which is in world-space.
+    pub fn cover(&self, progress: f32, focus: Vec2, camera: &Camera) -> Option<ScreenCover> {
+        // How far the screen is covered up.
+        let (style, amount) = match self {
+            Step::TitleCard(title) => return Some(ScreenCover::TitleCard(title.clone())),
+            Step::CoverOut(style) => (*style, progress),
+            Step::CoverIn(style) => (*style, 1.0 - progress),
+            _ => return None,
+        };
+
+        match style {
+            TransitionStyle::Fade => Some(ScreenCover::Fade(amount)),
+            TransitionStyle::Iris => Some(ScreenCover::Iris {
+                center: focus,
+                // The circle starts out large enough to show the whole screen
+                // wherever the focus is on it.
+                radius: camera.size.length() * (1.0 - amount),
+            }),
+            TransitionStyle::Cut => None,
+        }
+    }
+}
+
+/// A sequence of steps that are played one after the other.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TransitionState {
+    steps: VecDeque<Step>,
+    /// How long the current step has been playing.
+    elapsed: Duration,
+}
+
+impl TransitionState {
+    pub fn new(steps: impl IntoIterator<Item = Step>) -> Self {
+        Self {
+            steps: steps.into_iter().collect(),
+            elapsed: Duration::ZERO,
+        }
+    }
+
+    /// Plays `step` once all the other steps have finished.
+    pub fn push(&mut self, step: Step) {
+        self.steps.push_back(step);
+    }
+
+    /// Returns whether every step has finished playing.
+    pub fn is_finished(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    /// Moves the transition `delta` forward and returns the steps that
+    /// finished, in the order they were played.
+    pub fn advance(&mut self, delta: Duration) -> Vec<Step> {
+        self.elapsed += delta;
+
+        let mut finished = Vec::new();
+        while let Some(step) = self.steps.front()
+            && self.elapsed >= step.duration()
+        {
+            self.elapsed -= step.duration();
+            finished.extend(self.steps.pop_front());
+        }
+
+        // Steps that are added later start from the beginning.
+        if self.steps.is_empty() {
+            self.elapsed = Duration::ZERO;
+        }
+
+        finished
+    }
+
+    /// Returns the step that is playing and how far along it is, from 0 to 1.
+    pub fn current(&self) -> Option<(&Step, f32)> {
+        let step = self.steps.front()?;
+        let progress = self.elapsed.as_secs_f32() / step.duration().as_secs_f32();
+        Some((step, progress.min(1.0)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_steps_finish_in_order() {
+        let mut transition = TransitionState::new([
+            Step::CoverOut(TransitionStyle::Fade),
+            Step::LoadSegment(1),
+            Step::CoverIn(TransitionStyle::Fade),
+        ]);
+
+        assert!(transition.advance(Duration::from_millis(200)).is_empty());
+        let (step, progress) = transition.current().unwrap();
+        assert_eq!(step, &Step::CoverOut(TransitionStyle::Fade));
+        assert_eq!(progress, 0.5);
+
+        // Steps that take no time finish together with the step before them.
+        let finished = transition.advance(Duration::from_millis(300));
+        assert_eq!(
+            finished,
+            [Step::CoverOut(TransitionStyle::Fade), Step::LoadSegment(1)]
+        );
+        assert_eq!(transition.current().unwrap().1, 0.25);
+
+        transition.advance(Duration::from_secs(1));
+        assert!(transition.is_finished());
+        assert_eq!(transition.current(), None);
+    }
+}